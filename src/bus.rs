@@ -0,0 +1,56 @@
+use framebuffer;
+use gpu;
+use mmu;
+
+/* The cpu's view of the rest of the gameboy while it runs an instruction.
+ *
+ * Every read and write the cpu makes takes an M-cycle (4 cycles) and so do the internal steps of
+ * an instruction that don't touch memory. After each of those the rest of the hardware (timer,
+ * serial, DMA and ppu) is run forward by the M-cycle, so an access part way through an
+ * instruction sees the registers as they would be at that point on hardware rather than as they
+ * were when the instruction started.
+ */
+pub struct Bus<'a> {
+    pub mmu: &'a mut mmu::MMU,
+    gpu: &'a mut gpu::GPU,
+    framebuffer: &'a mut framebuffer::Framebuffer,
+    cycles: u8,
+}
+
+impl<'a> Bus<'a> {
+    pub fn get(&mut self, address: u16) -> u8 {
+        let v = self.mmu.get(address);
+        self.idle();
+        v
+    }
+
+    pub fn set(&mut self, address: u16, v: u8) {
+        self.mmu.set(address, v);
+        self.idle();
+    }
+
+    /* An M-cycle where the cpu is busy with something other than the bus */
+    pub fn idle(&mut self) {
+        self.cycles += 4;
+        self.mmu.tick(4);
+        self.gpu.tick(self.mmu, 4, self.framebuffer);
+    }
+
+    /* The cycles run through the bus since it was created */
+    pub fn cycles(&self) -> u8 {
+        self.cycles
+    }
+}
+
+pub fn new<'a>(
+    mmu: &'a mut mmu::MMU,
+    gpu: &'a mut gpu::GPU,
+    framebuffer: &'a mut framebuffer::Framebuffer,
+) -> Bus<'a> {
+    Bus {
+        mmu: mmu,
+        gpu: gpu,
+        framebuffer: framebuffer,
+        cycles: 0,
+    }
+}
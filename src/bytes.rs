@@ -39,11 +39,7 @@ pub fn clear_bit(input: u8, n: u8) -> u8 {
 }
 
 pub fn add_unsigned_signed(unsigned: u16, signed: i8) -> u16 {
-    if signed < 0 {
-        unsigned - (-signed as u16)
-    } else {
-        unsigned + (signed as u16)
-    }
+    unsigned.wrapping_add(signed as i16 as u16)
 }
 
 #[cfg(test)]
@@ -59,5 +55,7 @@ mod tests {
     #[test]
     fn test_add_unsigned_signed() {
         assert_eq!(add_unsigned_signed(50 as u16, -13 as i8), 37);
+        assert_eq!(add_unsigned_signed(0x0100, -128), 0x0080);
+        assert_eq!(add_unsigned_signed(0xFFFF, 1), 0x0000);
     }
 }
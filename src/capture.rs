@@ -0,0 +1,148 @@
+use std::io;
+use std::path::Path;
+
+use device::apu;
+use framebuffer;
+use gameboy;
+use palette;
+use resampler;
+use wav;
+
+/* A gameboy's audio rendered without a display, as 16 bit WAV files would hold it.
+ *
+ * It goes through the same resampling and DC blocking as the audio the display plays, but as
+ * nothing here depends on the host's clock the same rom always renders the same samples.
+ */
+pub struct Recording {
+    pub rate: u32,
+    /* interleaved left and right */
+    pub mixed: Vec<f32>,
+    /* channels 1-4 on their own, before panning and master volume, scaled as the mix scales
+     * them, when they were asked for
+     */
+    pub stems: Option<[Vec<f32>; 4]>,
+}
+
+impl Recording {
+    /* Writes the mixed output to `path`, and each stem next to it with a .ch1-4.wav extension */
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        wav::write(path, self.rate, 2, &self.mixed)?;
+
+        if let Some(ref stems) = self.stems {
+            for (i, stem) in stems.iter().enumerate() {
+                let stem_path = path.with_extension(format!("ch{}.wav", i + 1));
+                wav::write(&stem_path, self.rate, 1, stem)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/* Runs `gameboy` for `frames` frames recording what the APU plays at `rate` */
+pub fn record(gameboy: &mut gameboy::Gameboy, frames: u32, rate: u32, stems: bool) -> Recording {
+    let width = if stems { 6 } else { 2 };
+
+    let mut resampler = resampler::new(width, apu::SAMPLE_RATE, rate);
+    let mut dc_blocker = resampler::new_dc_blocker(width, rate);
+    let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+    let mut input = Vec::new();
+    let mut output = Vec::new();
+
+    /* whatever played before now isn't part of the recording */
+    gameboy.take_samples();
+
+    for _ in 0..frames {
+        while !gameboy.next_instruction(&mut framebuffer) {}
+
+        input.clear();
+        for s in gameboy.take_samples() {
+            input.push(s.left);
+            input.push(s.right);
+            if stems {
+                input.extend_from_slice(&s.channels);
+            }
+        }
+        resampler.process(&input, &mut output);
+    }
+
+    dc_blocker.process(&mut output);
+
+    let mut mixed = Vec::with_capacity(output.len() / width * 2);
+    for frame in output.chunks(width) {
+        mixed.push(frame[0]);
+        mixed.push(frame[1]);
+    }
+
+    /* stems are scaled like the mix's share of each channel, which leaves room for the filters'
+     * overshoot on a full -1.0 to 1.0 swing
+     */
+    let stems = if stems {
+        let mut channels = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for frame in output.chunks(width) {
+            for (c, channel) in channels.iter_mut().enumerate() {
+                channel.push(frame[2 + c] / 4.0);
+            }
+        }
+        Some(channels)
+    } else {
+        None
+    };
+
+    Recording {
+        rate: rate,
+        mixed: mixed,
+        stems: stems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config;
+
+    /* Plays a 512Hz square wave on channel 2 */
+    fn square_wave() -> gameboy::Gameboy {
+        let program = [
+            0x3E, 0x80, 0xE0, 0x26, // NR52: power on
+            0x3E, 0x77, 0xE0, 0x24, // NR50: full volume
+            0x3E, 0x22, 0xE0, 0x25, // NR51: channel 2 left and right
+            0x3E, 0x80, 0xE0, 0x16, // NR21: 50% duty
+            0x3E, 0xF0, 0xE0, 0x17, // NR22: volume 15
+            0x3E, 0x00, 0xE0, 0x18, // NR23
+            0x3E, 0x87, 0xE0, 0x19, // NR24: trigger at 0x700
+            0x18, 0xFE,             // JR -2
+        ];
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+
+        gameboy::from_roms(&config::zero(), &[], rom).unwrap()
+    }
+
+    #[test]
+    fn test_record_stems() {
+        let recording = record(&mut square_wave(), 10, 48000, true);
+        let stems = recording.stems.unwrap();
+
+        // 10 frames at ~59.7fps
+        assert!(recording.mixed.len() > 8000 * 2);
+        assert_eq!(recording.mixed.len(), stems[1].len() * 2);
+
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |m, v| v.abs().max(m));
+        assert!(peak(&recording.mixed) > 0.1);
+        assert_eq!(peak(&stems[0]), 0.0);
+        assert!(peak(&stems[1]) > 0.2 && peak(&stems[1]) < 0.5);
+        assert_eq!(peak(&stems[2]), 0.0);
+        assert_eq!(peak(&stems[3]), 0.0);
+    }
+
+    #[test]
+    fn test_record_is_deterministic() {
+        let a = record(&mut square_wave(), 5, 44100, false);
+        let b = record(&mut square_wave(), 5, 44100, false);
+
+        assert!(a.stems.is_none());
+        assert_eq!(a.mixed, b.mixed);
+    }
+}
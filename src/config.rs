@@ -48,6 +48,12 @@ pub fn debug_default() -> Debug {
 pub struct Config {
     pub boot_rom: String,
     pub game_rom: String,
+    /* Where battery backed cartridge ram is persisted, defaults to the game rom with a .sav
+     * extension.
+     */
+    pub save_file: String,
+    /* Catch the cartridge clock up with the time that passed on the host since the last save */
+    pub rtc_wall_clock: bool,
     pub debug: Debug,
 }
 
@@ -68,6 +74,8 @@ impl Config {
 pub fn new(
     boot_rom_path:&str,
     game_rom_path:&str,
+    save_file_path: Option<&str>,
+    rtc_wall_clock: bool,
     debug: Debug
 ) -> Result<Config, String> {
     if !Path::new(boot_rom_path).exists() {
@@ -78,18 +86,38 @@ pub fn new(
         return Err(format!("Game rom path does not exist: {}", game_rom_path));
     }
 
+    let save_file = match save_file_path {
+        Some(p) => String::from(p),
+        None => Path::new(game_rom_path).with_extension("sav").to_string_lossy().into_owned(),
+    };
+
     Ok(Config {
         boot_rom: String::from(boot_rom_path),
         game_rom: String::from(game_rom_path),
+        save_file: save_file,
+        rtc_wall_clock: rtc_wall_clock,
         debug: debug,
     })
 }
 
+/* GBS files are played without a boot rom and have nothing to save */
+pub fn gbs(gbs_path: &str, debug: Debug) -> Config {
+    Config {
+        boot_rom: String::new(),
+        game_rom: String::from(gbs_path),
+        save_file: String::new(),
+        rtc_wall_clock: false,
+        debug: debug,
+    }
+}
+
 #[cfg(test)]
 pub fn zero() -> Config {
     Config {
         boot_rom: String::from("test_boot_rom"),
         game_rom: String::from("test_game_rom"),
+        save_file: String::from("test_game_rom.sav"),
+        rtc_wall_clock: false,
         debug: debug_default(),
     }
 }
@@ -1,4 +1,7 @@
+use bus;
 use config;
+use device::Device;
+use device::interrupt;
 use instructions;
 use mmu;
 use registers;
@@ -8,12 +11,21 @@ use registers::Flag;
 #[derive(PartialEq)]
 pub enum State {
     Halted,
+    Stopped,
     Running,
     Prefix,
+    /* An illegal opcode hangs the cpu for good, not even an interrupt gets it going again */
+    Locked,
 }
 
 pub struct CPU {
     state: State,
+    /* set by HALT when it trips the halt bug, the next fetch doesn't move PC on */
+    halt_bug: bool,
+    /* EI only turns interrupts on after the instruction following it, this counts down the
+     * instructions left until it does
+     */
+    enable_interrupts: u8,
     log_instructions: bool,
     log_register_states: bool,
 }
@@ -38,6 +50,16 @@ impl Context {
     }
 }
 
+/* The highest priority interrupt that's both requested and enabled */
+fn pending_interrupt(mmu: &mmu::MMU) -> Option<interrupt::Flag> {
+    let enabled = mmu.interrupt_enable.get_enabled_interrupts();
+    mmu.hardware_io.interrupts.get_interrupt(enabled)
+}
+
+fn interrupt_pending(mmu: &mmu::MMU) -> bool {
+    pending_interrupt(mmu).is_some()
+}
+
 fn log_register_states(registers: &registers::Registers) {
     let pc = registers.get16(&Registers16::PC);
     let af = registers.get16(&Registers16::AF);
@@ -78,25 +100,85 @@ impl CPU {
         self.log_instructions = state;
     }
 
+    /* Runs the next step of the cpu, an instruction, a CB prefixed opcode, an interrupt dispatch
+     * or an M-cycle of being halted, and returns the number of cycles it took. The rest of the
+     * hardware is run forward through the bus as it goes.
+     */
     pub fn tick(
         &mut self,
         instructions: &instructions::Instructions,
         registers: &mut registers::Registers,
-        mmu: &mut mmu::MMU,
+        bus: &mut bus::Bus,
     ) -> u8 {
+        let start = bus.cycles();
+
         match self.state {
             State::Running => {
+                /* interrupts are dispatched between instructions, never between a CB prefix
+                 * and its opcode
+                 */
+                if registers.get_interrupts_enabled() {
+                    if let Some(f) = pending_interrupt(bus.mmu) {
+                        registers.set_interrupts_enabled(false);
+                        interrupt::handle_interrupt(registers, bus, f);
+                        return bus.cycles() - start;
+                    }
+                }
+
                 let mut context = Context::new();
-                let instruction = self.fetch(&mut context, instructions, registers, mmu, false);
-                self.execute(&mut context, &instruction, registers, mmu)
+                let instruction = self.fetch(&mut context, instructions, registers, bus, false);
+                self.execute(&mut context, &instruction, registers, bus);
+                self.instruction_done(registers);
             }
             State::Prefix => {
                 let mut context = Context::new();
-                let instruction = self.fetch(&mut context, instructions, registers, mmu, true);
-                self.execute(&mut context, &instruction, registers, mmu)
+                let instruction = self.fetch(&mut context, instructions, registers, bus, true);
+                self.execute(&mut context, &instruction, registers, bus);
+                self.instruction_done(registers);
+            }
+            /* HALT keeps the clock running and wakes up as soon as an enabled interrupt is
+             * pending, it's up to IME whether that interrupt is then serviced.
+             */
+            State::Halted => {
+                if interrupt_pending(bus.mmu) {
+                    self.state = State::Running;
+                }
+                bus.idle();
+            }
+            State::Locked => bus.idle(),
+            /* STOP switches the clock off until a button on a selected joypad line is pressed,
+             * nothing else runs so the bus isn't ticked
+             */
+            State::Stopped => {
+                if bus.mmu.hardware_io.joypad.get() & 0x0F != 0x0F {
+                    self.state = State::Running;
+                }
+                return 4;
             }
-            State::Halted => 0,
         }
+
+        bus.cycles() - start
+    }
+
+    fn instruction_done(&mut self, registers: &mut registers::Registers) {
+        if self.state == State::Prefix || self.enable_interrupts == 0 {
+            return;
+        }
+
+        self.enable_interrupts -= 1;
+        if self.enable_interrupts == 0 {
+            registers.set_interrupts_enabled(true);
+        }
+    }
+
+    /* True once the cpu has run into an illegal opcode, the rest of the hardware carries on */
+    pub fn locked(&self) -> bool {
+        self.state == State::Locked
+    }
+
+    /* While stopped nothing but the cpu is running, the timer and ppu are frozen */
+    pub fn stopped(&self) -> bool {
+        self.state == State::Stopped
     }
 
     fn fetch(
@@ -104,16 +186,20 @@ impl CPU {
         context: &mut Context,
         instructions: &instructions::Instructions,
         registers: &mut registers::Registers,
-        mmu: &mut mmu::MMU,
+        bus: &mut bus::Bus,
         prefix: bool,
     ) -> instructions::Op {
         let pc = registers.get16(&Registers16::PC);
-        let opcode = mmu.get(pc);
+        let opcode = bus.get(pc);
 
         context.pc = pc;
         context.opcode = opcode;
 
-        registers.inc_pc();
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            registers.inc_pc();
+        }
 
         if prefix {
             *instructions.get_cb(opcode)
@@ -127,35 +213,63 @@ impl CPU {
         context: &mut Context,
         instruction: &instructions::Op,
         mut registers: &mut registers::Registers,
-        mmu: &mut mmu::MMU,
-    ) -> u8 {
+        bus: &mut bus::Bus,
+    ) {
         match instruction {
             instructions::Op::PrefixCB => {
                 self.state = State::Prefix;
                 if self.log_register_states {
                     log_register_states(&registers);
                 }
-                0
             }
+            /* On the DMG a HALT with IME off and an interrupt already pending doesn't halt at all,
+             * instead PC isn't incremented after the next opcode is fetched so the byte after
+             * the HALT is read twice.
+             */
             instructions::Op::HALT => {
-                self.state = State::Halted;
-                0
+                if !registers.get_interrupts_enabled() && interrupt_pending(bus.mmu) {
+                    self.halt_bug = true;
+                } else {
+                    self.state = State::Halted;
+                }
+            }
+            /* counts itself and the instruction after it */
+            instructions::Op::EI => {
+                self.enable_interrupts = 2;
+            }
+            instructions::Op::DI => {
+                self.enable_interrupts = 0;
+                registers.set_interrupts_enabled(false);
+            }
+            /* STOP is followed by a padding byte, entering it resets DIV. Neither takes a bus
+             * cycle, the clock stops before the padding byte would be read, and the reset goes
+             * straight to the timer so an OAM DMA cutting the cpu off from the bus can't drop it.
+             */
+            instructions::Op::STOP => {
+                registers.inc_pc();
+                bus.mmu.hardware_io.timer.set(0, 0);
+                self.state = State::Stopped;
+            }
+            instructions::Op::Illegal => {
+                if self.log_instructions {
+                    println!("Illegal opcode {:02X} at {:04X}, the cpu has locked up", context.opcode, context.pc);
+                }
+                self.state = State::Locked;
             }
             instructions::Op::NotImplemented => {
                 context.cb = self.state == State::Prefix;
                 log_context(&context, &registers);
-                // panic!("Not Implemented");
-                0
+                panic!("Opcode {:02X} is missing from the instruction table", context.opcode);
             }
             _ => {
                 let mut args = Vec::new();
                 for _ in 0..instruction.args() {
                     let next = registers.get16(&Registers16::PC);
-                    args.push(mmu.get(next));
+                    args.push(bus.get(next));
                     registers.inc_pc()
                 }
 
-                let cycles = instruction.call(&mut registers, mmu, &args);
+                instruction.call(&mut registers, bus, &args);
 
                 context.instruction = *instruction;
                 context.args = args;
@@ -167,7 +281,6 @@ impl CPU {
                     log_register_states(&registers);
                 }
                 self.state = State::Running;
-                cycles
             }
         }
     }
@@ -176,6 +289,8 @@ impl CPU {
 pub fn new(config: config::Config) -> CPU {
     CPU {
         state: State::Running,
+        halt_bug: false,
+        enable_interrupts: 0,
         log_instructions: config.debug.log_instructions,
         log_register_states: config.debug.log_register_states,
     }
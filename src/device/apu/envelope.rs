@@ -0,0 +1,66 @@
+/* The volume envelope of the square and noise channels, NRx2:
+ *
+ * Bits 7-4: Initial volume
+ * Bit 3:    Direction (0=down, 1=up)
+ * Bits 2-0: Period, the volume moves by one every n clocks from the frame sequencer (0=never)
+ *
+ * The register also powers the channel's DAC, which is on whenever any of the top 5 bits are.
+ */
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn get(&self) -> u8 {
+        self.register
+    }
+
+    pub fn set(&mut self, v: u8) {
+        self.register = v;
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.register & 0x07;
+    }
+
+    pub fn clock(&mut self) {
+        let period = self.register & 0x07;
+        if period == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer > 0 {
+            return;
+        }
+
+        self.timer = period;
+
+        if self.register & 0x08 != 0 && self.volume < 15 {
+            self.volume += 1;
+        } else if self.register & 0x08 == 0 && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+pub fn new() -> Envelope {
+    Envelope {
+        register: 0,
+        volume: 0,
+        timer: 0,
+    }
+}
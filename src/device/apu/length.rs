@@ -0,0 +1,68 @@
+/* A channel's length counter.
+ *
+ * NRx1 loads it with the length to play for and Bit 6 of NRx4 enables it, after which every
+ * clock from the frame sequencer counts it down and the channel is switched off when it runs
+ * out. Triggering the channel with the counter run out reloads it with the full length.
+ *
+ * Enabling the counter when the frame sequencer's next step won't clock it clocks it straight
+ * away, which also takes one off the full length a trigger on that write reloads.
+ */
+pub struct Length {
+    full: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn load(&mut self, n: u8) {
+        self.counter = self.full - n as u16;
+    }
+
+    /* Handles a write to NRx4, returns false when the extra clock runs the counter out and the
+     * channel isn't being triggered to start it again.
+     */
+    pub fn set(&mut self, v: u8, frame_sequencer: u8) -> bool {
+        let extra_clock = frame_sequencer % 2 == 1;
+        let enabled = v & 0x40 != 0;
+        let trigger = v & 0x80 != 0;
+        let mut running = true;
+
+        if extra_clock && enabled && !self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            running = self.counter > 0 || trigger;
+        }
+        self.enabled = enabled;
+
+        if trigger && self.counter == 0 {
+            self.counter = if extra_clock && enabled { self.full - 1 } else { self.full };
+        }
+
+        running
+    }
+
+    /* Powering the APU off clears NRx4's enable, on the DMG the counter itself is left alone */
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+
+    /* Returns false once the counter has run out */
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter > 0;
+        }
+        true
+    }
+}
+
+pub fn new(full: u16) -> Length {
+    Length {
+        full: full,
+        counter: 0,
+        enabled: false,
+    }
+}
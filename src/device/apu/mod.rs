@@ -0,0 +1,420 @@
+use std::mem;
+
+use device::Device;
+
+pub mod envelope;
+pub mod length;
+pub mod noise;
+pub mod square;
+pub mod wave;
+
+/* The APU is run an M-cycle at a time and takes a sample after each */
+pub const SAMPLE_RATE: u32 = 1048576;
+
+/* If nothing is taking the samples there's no point holding on to more than a second of them */
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize;
+
+/* The bits of each register that always read as 1, write only and unused bits included */
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+/* One sample of the APU's output, each value is between -1.0 and 1.0 */
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub left: f32,
+    pub right: f32,
+    /* each channel's DAC output before it's panned and mixed */
+    pub channels: [f32; 4],
+}
+
+/* The Audio Processing Unit, addressed relative to 0xFF10:
+ *
+ * 0x00-0x04: NR10-NR14 - Channel 1, square wave with frequency sweep
+ * 0x05-0x09: NR20-NR24 - Channel 2, square wave (there's no NR20)
+ * 0x0A-0x0E: NR30-NR34 - Channel 3, wave
+ * 0x0F-0x13: NR40-NR44 - Channel 4, noise (there's no NR40)
+ * 0x14:      NR50 - Bits 6-4 left volume, Bits 2-0 right volume
+ * 0x15:      NR51 - Bits 7-4 channels 4-1 to the left, Bits 3-0 channels 4-1 to the right
+ * 0x16:      NR52 - Bit 7 power, Bits 3-0 channels 4-1 playing (read only)
+ * 0x20-0x2F: Wave RAM
+ *
+ * The channels' frequency timers run every cycle, their length counters, volume envelopes and
+ * channel 1's sweep are clocked by the frame sequencer. The frame sequencer steps at 512Hz off the
+ * timer's divider and goes through 8 steps:
+ *
+ * Step   Length   Sweep   Envelope
+ * 0      Clock    -       -
+ * 1      -        -       -
+ * 2      Clock    Clock   -
+ * 3      -        -       -
+ * 4      Clock    -       -
+ * 5      -        -       -
+ * 6      Clock    Clock   -
+ * 7      -        -       Clock
+ *
+ * Each channel's DAC turns its 0-15 output level into -1.0 to 1.0 (or 0.0 while the DAC is off),
+ * those are added up for each side according to NR51 and scaled by NR50's volume.
+ *
+ * Powering the APU off clears every register and ignores writes other than to NR52 and wave RAM
+ * until it's powered on again, when the frame sequencer starts again from step 0. The DMG leaves
+ * the length counters out of that, they keep their counts and NRx1 writes still load them.
+ */
+pub struct APU {
+    pub square_1: square::Square,
+    pub square_2: square::Square,
+    pub wave: wave::Wave,
+    pub noise: noise::Noise,
+    volume: u8,
+    panning: u8,
+    enabled: bool,
+    /* the step the frame sequencer runs next */
+    frame_sequencer: u8,
+    samples: Vec<Sample>,
+}
+
+impl Device for APU {
+    fn get(&self, a: u16) -> u8 {
+        let v = match a {
+            0x00..=0x04 => self.square_1.get(a),
+            0x05..=0x09 => self.square_2.get(a - 0x05),
+            0x0A..=0x0E => self.wave.get(a - 0x0A),
+            0x0F => 0,
+            0x10..=0x13 => self.noise.get(a - 0x10),
+            0x14 => self.volume,
+            0x15 => self.panning,
+            0x16 => self.status(),
+            0x17..=0x1F => 0,
+            0x20..=0x2F => return self.wave.get_ram(a - 0x20),
+            _ => panic!("apu address out of range: {:X}", a),
+        };
+
+        v | READ_MASKS[a as usize]
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        if !self.enabled && a < 0x16 {
+            match a {
+                0x01 => self.square_1.load_length(v),
+                0x06 => self.square_2.load_length(v),
+                0x0B => self.wave.load_length(v),
+                0x10 => self.noise.load_length(v),
+                _ => {}
+            }
+            return;
+        }
+
+        let frame_sequencer = self.frame_sequencer;
+
+        match a {
+            0x00..=0x04 => self.square_1.set(a, v, frame_sequencer),
+            0x06..=0x09 => self.square_2.set(a - 0x05, v, frame_sequencer),
+            0x0A..=0x0E => self.wave.set(a - 0x0A, v, frame_sequencer),
+            0x10..=0x13 => self.noise.set(a - 0x10, v, frame_sequencer),
+            0x14 => self.volume = v,
+            0x15 => self.panning = v,
+            0x16 => {
+                let enabled = v & 0x80 != 0;
+                if self.enabled && !enabled {
+                    self.power_off();
+                } else if !self.enabled && enabled {
+                    self.frame_sequencer = 0;
+                }
+                self.enabled = enabled;
+            }
+            0x05 | 0x0F | 0x17..=0x1F => {}
+            0x20..=0x2F => self.wave.set_ram(a - 0x20, v),
+            _ => panic!("apu address out of range: {:X}", a),
+        }
+    }
+}
+
+/* A channel's DAC output */
+fn dac(dac_enabled: bool, output: u8) -> f32 {
+    if dac_enabled {
+        output as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+impl APU {
+    fn status(&self) -> u8 {
+        let mut v = if self.enabled { 0x80 } else { 0 };
+
+        if self.square_1.enabled() {
+            v |= 0x01;
+        }
+        if self.square_2.enabled() {
+            v |= 0x02;
+        }
+        if self.wave.enabled() {
+            v |= 0x04;
+        }
+        if self.noise.enabled() {
+            v |= 0x08;
+        }
+
+        v
+    }
+
+    fn power_off(&mut self) {
+        self.square_1.power_off();
+        self.square_2.power_off();
+        self.wave.power_off();
+        self.noise.power_off();
+        self.volume = 0;
+        self.panning = 0;
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer;
+
+        if step % 2 == 0 {
+            self.square_1.clock_length();
+            self.square_2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+
+        if step == 2 || step == 6 {
+            self.square_1.clock_sweep();
+        }
+
+        if step == 7 {
+            self.square_1.clock_envelope();
+            self.square_2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_sequencer = (step + 1) % 8;
+    }
+
+    fn sample(&self) -> Sample {
+        if !self.enabled {
+            return Sample {
+                left: 0.0,
+                right: 0.0,
+                channels: [0.0; 4],
+            };
+        }
+
+        let channels = [
+            dac(self.square_1.dac_enabled(), self.square_1.output()),
+            dac(self.square_2.dac_enabled(), self.square_2.output()),
+            dac(self.wave.dac_enabled(), self.wave.output()),
+            dac(self.noise.dac_enabled(), self.noise.output()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (i, c) in channels.iter().enumerate() {
+            if self.panning & (0x10 << i) != 0 {
+                left += c;
+            }
+            if self.panning & (0x01 << i) != 0 {
+                right += c;
+            }
+        }
+
+        let left_volume = ((self.volume >> 4) & 0x07) as f32 + 1.0;
+        let right_volume = (self.volume & 0x07) as f32 + 1.0;
+
+        Sample {
+            left: left / 4.0 * left_volume / 8.0,
+            right: right / 4.0 * right_volume / 8.0,
+            channels: channels,
+        }
+    }
+
+    /* Runs the frame sequencer for the clocks the divider has given it, then the channels forward
+     * by `cycles` an M-cycle at a time taking a sample after each.
+     */
+    pub fn tick(&mut self, cycles: u32, frame_sequencer_clocks: u8) {
+        if self.enabled {
+            for _ in 0..frame_sequencer_clocks {
+                self.clock_frame_sequencer();
+            }
+        }
+
+        for _ in 0..cycles / 4 {
+            if self.enabled {
+                self.square_1.tick(4);
+                self.square_2.tick(4);
+                self.wave.tick(4);
+                self.noise.tick(4);
+            }
+
+            if self.samples.len() < MAX_SAMPLES {
+                let sample = self.sample();
+                self.samples.push(sample);
+            }
+        }
+    }
+
+    /* The samples taken since this was last called, at SAMPLE_RATE */
+    pub fn take_samples(&mut self) -> Vec<Sample> {
+        mem::take(&mut self.samples)
+    }
+}
+
+pub fn new() -> APU {
+    APU {
+        square_1: square::new_with_sweep(),
+        square_2: square::new_without_sweep(),
+        wave: wave::new(),
+        noise: noise::new(),
+        volume: 0,
+        panning: 0,
+        enabled: false,
+        frame_sequencer: 0,
+        samples: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powered() -> APU {
+        let mut apu = new();
+        apu.set(0x16, 0x80);
+        apu
+    }
+
+    #[test]
+    fn test_frame_sequencer_clocks_length() {
+        let mut apu = powered();
+
+        // length of 2 with length enabled
+        apu.set(0x06, 0x3E);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0xC0);
+        assert_eq!(apu.get(0x16), 0xF2);
+
+        // steps 0 and 2 clock length, 1 doesn't
+        apu.tick(4, 2);
+        assert!(apu.square_2.enabled());
+        apu.tick(4, 1);
+        assert!(!apu.square_2.enabled());
+        assert_eq!(apu.get(0x16), 0xF0);
+    }
+
+    #[test]
+    fn test_register_read_back() {
+        let mut apu = powered();
+
+        for a in 0x00..0x16 {
+            apu.set(a, 0x00);
+        }
+        for a in 0x00..0x20 {
+            let power = if a == 0x16 { 0x80 } else { 0x00 };
+            assert_eq!(apu.get(a), READ_MASKS[a as usize] | power, "{:X}", a);
+        }
+
+        for a in 0x00..0x16 {
+            apu.set(a, 0xFF);
+        }
+        for a in 0x00..0x16 {
+            assert_eq!(apu.get(a), 0xFF, "{:X}", a);
+        }
+    }
+
+    #[test]
+    fn test_power_off_clears_registers() {
+        let mut apu = powered();
+
+        apu.set(0x14, 0x77);
+        apu.set(0x15, 0xFF);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0x80);
+        apu.set(0x20, 0x5A);
+
+        apu.set(0x16, 0x00);
+        assert_eq!(apu.get(0x14), 0x00);
+        assert_eq!(apu.get(0x15), 0x00);
+        assert_eq!(apu.get(0x07), 0x00);
+        assert_eq!(apu.get(0x16), 0x70);
+        assert_eq!(apu.get(0x20), 0x5A);
+
+        // writes are ignored until it's powered on
+        apu.set(0x14, 0x77);
+        assert_eq!(apu.get(0x14), 0x00);
+        apu.set(0x16, 0x80);
+        apu.set(0x14, 0x77);
+        assert_eq!(apu.get(0x14), 0x77);
+    }
+
+    #[test]
+    fn test_power_off_keeps_length() {
+        let mut apu = powered();
+
+        // a length of 2 survives power off
+        apu.set(0x06, 0x3E);
+        apu.set(0x16, 0x00);
+        apu.set(0x16, 0x80);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0xC0);
+        apu.tick(4, 1);
+        assert!(apu.square_2.enabled());
+        apu.tick(4, 2);
+        assert!(!apu.square_2.enabled());
+
+        // with the power off NR21 only loads the length, a length of 1 and no duty
+        apu.set(0x16, 0x00);
+        apu.set(0x06, 0xFF);
+        assert_eq!(apu.get(0x06), 0x3F);
+        apu.set(0x16, 0x80);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0xC0);
+        assert!(apu.square_2.enabled());
+        apu.tick(4, 1);
+        assert!(!apu.square_2.enabled());
+    }
+
+    #[test]
+    fn test_mixing() {
+        let mut apu = powered();
+
+        // channel 2 at full volume on the left only, both sides at full master volume
+        apu.set(0x14, 0x77);
+        apu.set(0x15, 0x20);
+        apu.set(0x06, 0x40);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0x87);
+
+        apu.take_samples();
+        apu.tick(4, 0);
+        let sample = apu.take_samples()[0];
+
+        // 25% duty starts high
+        assert_eq!(sample.channels, [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(sample.left, 0.25);
+        assert_eq!(sample.right, 0.0);
+    }
+
+    #[test]
+    fn test_master_volume() {
+        let mut apu = powered();
+
+        // channel 2 to both sides, left at full volume and right at the lowest
+        apu.set(0x14, 0x70);
+        apu.set(0x15, 0x22);
+        apu.set(0x06, 0x40);
+        apu.set(0x07, 0xF0);
+        apu.set(0x09, 0x87);
+
+        apu.take_samples();
+        apu.tick(4, 0);
+        let sample = apu.take_samples()[0];
+
+        assert_eq!(sample.left, 0.25);
+        assert_eq!(sample.right, 0.25 / 8.0);
+    }
+}
@@ -0,0 +1,198 @@
+use std::mem;
+
+use device::apu::envelope;
+use device::apu::length;
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/* The noise channel, addressed relative to NR41:
+ *
+ * 0: NR41 - Bits 5-0 length load (the channel plays for 64 - n)
+ * 1: NR42 - Volume envelope
+ * 2: NR43 - Bits 7-4 clock shift, Bit 3 width (1=7 bit), Bits 2-0 divisor code
+ * 3: NR44 - Bit 7 trigger, Bit 6 length enable
+ *
+ * The output comes from a 15 bit linear feedback shift register, shifted every divisor << shift
+ * cycles. Each shift XORs the bottom two bits and feeds the result in at the top, in 7 bit mode
+ * it's fed in at bit 6 as well giving a much shorter, more tonal, sequence. The channel is high
+ * while bit 0 is clear.
+ */
+pub struct Noise {
+    length: length::Length,
+    envelope: envelope::Envelope,
+    enabled: bool,
+    polynomial: u8,
+    lfsr: u16,
+    timer: u32,
+}
+
+impl Noise {
+    /* The bits of the registers that can be read, the APU fills in the rest */
+    pub fn get(&self, a: u16) -> u8 {
+        match a {
+            0 => 0,
+            1 => self.envelope.get(),
+            2 => self.polynomial,
+            3 => if self.length.enabled() { 0x40 } else { 0 },
+            _ => panic!("noise channel address out of range: {:X}", a),
+        }
+    }
+
+    /* `frame_sequencer` is the step the frame sequencer will run next */
+    pub fn set(&mut self, a: u16, v: u8, frame_sequencer: u8) {
+        match a {
+            0 => self.length.load(v & 0x3F),
+            1 => {
+                self.envelope.set(v);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            2 => self.polynomial = v,
+            3 => {
+                if !self.length.set(v, frame_sequencer) {
+                    self.enabled = false;
+                }
+
+                if v & 0x80 != 0 {
+                    self.enabled = self.dac_enabled();
+                    self.envelope.trigger();
+                    self.lfsr = 0x7FFF;
+                    self.timer = self.period();
+                }
+            }
+            _ => panic!("noise channel address out of range: {:X}", a),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[(self.polynomial & 0x07) as usize] << (self.polynomial >> 4)
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /* The channel's output level, 0-15 */
+    pub fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 0x01 == 0 {
+            self.envelope.volume()
+        } else {
+            0
+        }
+    }
+
+    fn shift(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.polynomial & 0x08 != 0 {
+            self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.shift();
+        }
+    }
+
+    /* The length part of a write to NR41, all that gets through while the APU is off */
+    pub fn load_length(&mut self, v: u8) {
+        self.length.load(v & 0x3F);
+    }
+
+    /* Powering the APU off clears the registers but not the length counter */
+    pub fn power_off(&mut self) {
+        let mut fresh = new();
+        mem::swap(&mut fresh.length, &mut self.length);
+        *self = fresh;
+        self.length.power_off();
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+pub fn new() -> Noise {
+    Noise {
+        length: length::new(64),
+        envelope: envelope::new(),
+        enabled: false,
+        polynomial: 0,
+        lfsr: 0x7FFF,
+        timer: 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence_length(polynomial: u8) -> usize {
+        let mut noise = new();
+
+        noise.set(1, 0xF0, 0);
+        noise.set(2, polynomial, 0);
+        noise.set(3, 0x80, 0);
+
+        // in 7 bit mode the starting state isn't part of the sequence, run into it first
+        for _ in 0..32768 {
+            noise.shift();
+        }
+
+        let start = noise.lfsr;
+        let mut n = 0;
+        loop {
+            noise.shift();
+            n += 1;
+            if noise.lfsr == start {
+                return n;
+            }
+        }
+    }
+
+    #[test]
+    fn test_lfsr_widths() {
+        assert_eq!(sequence_length(0x00), 32767);
+        assert_eq!(sequence_length(0x08), 127);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut noise = new();
+
+        noise.set(1, 0xA0, 0);
+        noise.set(3, 0x80, 0);
+        assert_eq!(noise.output(), 0);
+
+        // the first shift feeds a 0 in at the top, the next few clear bit 0
+        noise.tick(8);
+        assert_eq!(noise.lfsr, 0x3FFF);
+        for _ in 0..14 {
+            noise.tick(8);
+        }
+        assert_eq!(noise.lfsr & 0x01, 0);
+        assert_eq!(noise.output(), 10);
+    }
+}
@@ -0,0 +1,414 @@
+use std::mem;
+
+use device::apu::envelope;
+use device::apu::length;
+
+/* The waveforms of the four duty cycles, 12.5%, 25%, 50% and 75%, played from the high bit down */
+const DUTY_CYCLES: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/* Channel 1's frequency sweep.
+ *
+ * The sweep works on a shadow copy of the frequency taken when the channel is triggered. Every
+ * time its timer runs out the new frequency (the shadow shifted right and added to or subtracted
+ * from itself) is calculated, written back and then calculated again. Either calculation going
+ * past 2047 switches the channel off, even when the result isn't used.
+ */
+struct Sweep {
+    register: u8,
+    shadow: u16,
+    timer: u8,
+    enabled: bool,
+    /* a calculation has subtracted since the last trigger, clearing negate after that switches
+     * the channel off
+     */
+    negated: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn negate(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    /* a period of 0 counts as 8 */
+    fn reload_timer(&mut self) {
+        self.timer = match self.period() {
+            0 => 8,
+            p => p,
+        };
+    }
+
+    /* The next frequency, None when it overflows */
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift();
+
+        let frequency = if self.negate() {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+
+        if frequency > 2047 {
+            None
+        } else {
+            Some(frequency)
+        }
+    }
+}
+
+/* A pulse channel, addressed relative to NRx0:
+ *
+ * 0: NRx0 - Sweep (channel 1 only): Bits 6-4 period, Bit 3 negate, Bits 2-0 shift
+ * 1: NRx1 - Bits 7-6 duty cycle, Bits 5-0 length load (the channel plays for 64 - n)
+ * 2: NRx2 - Volume envelope
+ * 3: NRx3 - Frequency low 8 bits
+ * 4: NRx4 - Bit 7 trigger, Bit 6 length enable, Bits 2-0 frequency high 3 bits
+ *
+ * The frequency timer runs out every (2048 - frequency) * 4 cycles and steps through the 8
+ * positions of the duty cycle. The length counter, envelope and sweep are clocked by the frame
+ * sequencer. Switching the DAC off also switches the channel off.
+ */
+pub struct Square {
+    sweep: Option<Sweep>,
+    length: length::Length,
+    envelope: envelope::Envelope,
+    enabled: bool,
+    duty: u8,
+    duty_position: u8,
+    frequency: u16,
+    timer: u32,
+}
+
+impl Square {
+    /* The bits of the registers that can be read, the APU fills in the rest */
+    pub fn get(&self, a: u16) -> u8 {
+        match a {
+            0 => match self.sweep {
+                Some(ref sweep) => sweep.register,
+                None => 0,
+            },
+            1 => self.duty << 6,
+            2 => self.envelope.get(),
+            3 => 0,
+            4 => if self.length.enabled() { 0x40 } else { 0 },
+            _ => panic!("square channel address out of range: {:X}", a),
+        }
+    }
+
+    /* `frame_sequencer` is the step the frame sequencer will run next */
+    pub fn set(&mut self, a: u16, v: u8, frame_sequencer: u8) {
+        match a {
+            0 => {
+                if let Some(ref mut sweep) = self.sweep {
+                    sweep.register = v & 0x7F;
+                    if sweep.negated && !sweep.negate() {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = v >> 6;
+                self.length.load(v & 0x3F);
+            }
+            2 => {
+                self.envelope.set(v);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | v as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | ((v as u16 & 0x07) << 8);
+
+                if !self.length.set(v, frame_sequencer) {
+                    self.enabled = false;
+                }
+
+                if v & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => panic!("square channel address out of range: {:X}", a),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = true;
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        let mut overflow = false;
+
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.shadow = frequency;
+            sweep.negated = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+
+            if sweep.shift() != 0 {
+                overflow = sweep.calculate().is_none();
+            }
+        }
+
+        if overflow || !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /* The channel's output level, 0-15 */
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let high = DUTY_CYCLES[self.duty as usize] & (0x80 >> self.duty_position) != 0;
+
+        if high { self.envelope.volume() } else { 0 }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+    }
+
+    /* The length part of a write to NRx1, all that gets through while the APU is off */
+    pub fn load_length(&mut self, v: u8) {
+        self.length.load(v & 0x3F);
+    }
+
+    /* Powering the APU off clears the registers but not the length counter */
+    pub fn power_off(&mut self) {
+        let mut fresh = if self.sweep.is_some() { new_with_sweep() } else { new_without_sweep() };
+        mem::swap(&mut fresh.length, &mut self.length);
+        *self = fresh;
+        self.length.power_off();
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let mut frequency = None;
+        let mut overflow = false;
+
+        if let Some(ref mut sweep) = self.sweep {
+            sweep.timer -= 1;
+            if sweep.timer > 0 {
+                return;
+            }
+            sweep.reload_timer();
+
+            if !sweep.enabled || sweep.period() == 0 {
+                return;
+            }
+
+            match sweep.calculate() {
+                Some(f) => {
+                    if sweep.shift() != 0 {
+                        sweep.shadow = f;
+                        frequency = Some(f);
+                        overflow = sweep.calculate().is_none();
+                    }
+                }
+                None => overflow = true,
+            }
+        }
+
+        if let Some(f) = frequency {
+            self.frequency = f;
+        }
+        if overflow {
+            self.enabled = false;
+        }
+    }
+}
+
+fn new(sweep: Option<Sweep>) -> Square {
+    Square {
+        sweep: sweep,
+        length: length::new(64),
+        envelope: envelope::new(),
+        enabled: false,
+        duty: 0,
+        duty_position: 0,
+        frequency: 0,
+        timer: 8192,
+    }
+}
+
+/* Channel 1, with the frequency sweep */
+pub fn new_with_sweep() -> Square {
+    new(Some(Sweep {
+        register: 0,
+        shadow: 0,
+        timer: 8,
+        enabled: false,
+        negated: false,
+    }))
+}
+
+/* Channel 2, which has no sweep */
+pub fn new_without_sweep() -> Square {
+    new(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duty_cycle() {
+        let mut square = new_without_sweep();
+
+        // 50% duty, full volume, frequency 2047 so the timer runs out every 4 cycles
+        square.set(1, 0x80, 0);
+        square.set(2, 0xF0, 0);
+        square.set(3, 0xFF, 0);
+        square.set(4, 0x87, 0);
+
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            square.tick(4);
+            wave.push(square.output());
+        }
+
+        assert_eq!(wave, vec![0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut square = new_without_sweep();
+
+        square.set(1, 0x3E, 0);
+        square.set(2, 0xF0, 0);
+        square.set(4, 0xC0, 0);
+        assert!(square.enabled());
+
+        square.clock_length();
+        assert!(square.enabled());
+        square.clock_length();
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_envelope() {
+        let mut square = new_without_sweep();
+
+        // volume 2 going down every clock
+        square.set(2, 0x21, 0);
+        square.set(4, 0x80, 0);
+        assert_eq!(square.envelope.volume(), 2);
+
+        square.clock_envelope();
+        assert_eq!(square.envelope.volume(), 1);
+        square.clock_envelope();
+        square.clock_envelope();
+        assert_eq!(square.envelope.volume(), 0);
+
+        // no DAC, no channel
+        square.set(2, 0x00, 0);
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut square = new_with_sweep();
+
+        // period 1, adding, shift 1
+        square.set(0, 0x11, 0);
+        square.set(2, 0xF0, 0);
+        square.set(3, 0x00, 0);
+        square.set(4, 0x82, 0);
+
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x300);
+        assert!(square.enabled());
+
+        // 0x480 fits, the second calculation's 0x6C0 doesn't stop the channel
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x480);
+        assert!(square.enabled());
+
+        // the second calculation overflows, it stops the channel without being written back
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x6C0);
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_sweep_overflow_on_trigger() {
+        let mut square = new_with_sweep();
+
+        square.set(0, 0x01, 0);
+        square.set(2, 0xF0, 0);
+        square.set(3, 0xFF, 0);
+        square.set(4, 0x87, 0);
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_clearing_negate_after_subtracting() {
+        let mut square = new_with_sweep();
+
+        // period 1, subtracting, shift 1
+        square.set(0, 0x19, 0);
+        square.set(2, 0xF0, 0);
+        square.set(4, 0x84, 0);
+        assert!(square.enabled());
+
+        square.set(0, 0x11, 0);
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_enabling_length_clocks_it() {
+        let mut square = new_without_sweep();
+
+        square.set(1, 0x3F, 0);
+        square.set(2, 0xF0, 0);
+        square.set(4, 0x80, 0);
+
+        // the next step doesn't clock length, so enabling it does
+        square.set(4, 0x40, 1);
+        assert!(!square.enabled());
+    }
+}
@@ -0,0 +1,217 @@
+use std::mem;
+
+use device::apu::length;
+
+/* The wave channel, addressed relative to NR30:
+ *
+ * 0: NR30 - Bit 7 DAC power
+ * 1: NR31 - Length load (the channel plays for 256 - n)
+ * 2: NR32 - Bits 6-5 output level (0=mute, 1=100%, 2=50%, 3=25%)
+ * 3: NR33 - Frequency low 8 bits
+ * 4: NR34 - Bit 7 trigger, Bit 6 length enable, Bits 2-0 frequency high 3 bits
+ *
+ * It plays back the 32 4 bit samples in wave RAM (0xFF30-0xFF3F), high nibble first, moving on
+ * to the next every (2048 - frequency) * 2 cycles. The output level is a right shift of the
+ * sample.
+ *
+ * While the channel is playing it owns wave RAM, reads and writes land on the byte it's playing
+ * rather than the one addressed.
+ */
+pub struct Wave {
+    ram: [u8; 16],
+    length: length::Length,
+    enabled: bool,
+    dac: bool,
+    level: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample: u8,
+}
+
+impl Wave {
+    /* The bits of the registers that can be read, the APU fills in the rest */
+    pub fn get(&self, a: u16) -> u8 {
+        match a {
+            0 => if self.dac { 0x80 } else { 0 },
+            1 => 0,
+            2 => self.level << 5,
+            3 => 0,
+            4 => if self.length.enabled() { 0x40 } else { 0 },
+            _ => panic!("wave channel address out of range: {:X}", a),
+        }
+    }
+
+    /* `frame_sequencer` is the step the frame sequencer will run next */
+    pub fn set(&mut self, a: u16, v: u8, frame_sequencer: u8) {
+        match a {
+            0 => {
+                self.dac = v & 0x80 != 0;
+                if !self.dac {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(v),
+            2 => self.level = (v >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x0700) | v as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | ((v as u16 & 0x07) << 8);
+
+                if !self.length.set(v, frame_sequencer) {
+                    self.enabled = false;
+                }
+
+                if v & 0x80 != 0 {
+                    self.enabled = self.dac;
+                    self.timer = self.period();
+                    self.position = 0;
+                }
+            }
+            _ => panic!("wave channel address out of range: {:X}", a),
+        }
+    }
+
+    fn ram_index(&self, a: u16) -> usize {
+        if self.enabled {
+            (self.position / 2) as usize
+        } else {
+            a as usize
+        }
+    }
+
+    pub fn get_ram(&self, a: u16) -> u8 {
+        self.ram[self.ram_index(a)]
+    }
+
+    pub fn set_ram(&mut self, a: u16, v: u8) {
+        let i = self.ram_index(a);
+        self.ram[i] = v;
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /* The channel's output level, 0-15 */
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        match self.level {
+            0 => 0,
+            l => self.sample >> (l - 1),
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut cycles = cycles;
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+
+            let byte = self.ram[(self.position / 2) as usize];
+            self.sample = if self.position % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /* The length part of a write to NR31, all that gets through while the APU is off */
+    pub fn load_length(&mut self, v: u8) {
+        self.length.load(v);
+    }
+
+    /* Powering the APU off clears the registers, wave RAM and the length counter keep their
+     * contents
+     */
+    pub fn power_off(&mut self) {
+        let mut fresh = new();
+        fresh.ram = self.ram;
+        mem::swap(&mut fresh.length, &mut self.length);
+        *self = fresh;
+        self.length.power_off();
+    }
+}
+
+pub fn new() -> Wave {
+    Wave {
+        ram: [0; 16],
+        length: length::new(256),
+        enabled: false,
+        dac: false,
+        level: 0,
+        frequency: 0,
+        timer: 4096,
+        position: 0,
+        sample: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plays_wave_ram() {
+        let mut wave = new();
+
+        wave.set_ram(0, 0x12);
+        wave.set_ram(1, 0x34);
+
+        // DAC on, 100%, frequency 2047 so the timer runs out every 2 cycles
+        wave.set(0, 0x80, 0);
+        wave.set(2, 0x20, 0);
+        wave.set(3, 0xFF, 0);
+        wave.set(4, 0x87, 0);
+
+        let mut samples = Vec::new();
+        for _ in 0..3 {
+            wave.tick(2);
+            samples.push(wave.output());
+        }
+        assert_eq!(samples, vec![2, 3, 4]);
+
+        // 25% shifts the sample right by 2
+        wave.set(2, 0x60, 0);
+        assert_eq!(wave.output(), 1);
+    }
+
+    #[test]
+    fn test_ram_follows_the_playing_byte() {
+        let mut wave = new();
+
+        wave.set_ram(0x05, 0xAB);
+        assert_eq!(wave.get_ram(0x05), 0xAB);
+
+        wave.set(0, 0x80, 0);
+        wave.set(4, 0x80, 0);
+        assert_eq!(wave.get_ram(0x05), 0x00);
+
+        wave.power_off();
+        assert_eq!(wave.get_ram(0x05), 0xAB);
+    }
+}
@@ -1,5 +1,3 @@
-use std::fs::File;
-use std::io::Read;
 use device::Device;
 
 pub struct BootRom {
@@ -17,9 +15,11 @@ impl Device for BootRom {
 }
 
 
-pub fn new (f: &mut File) -> BootRom {
+/* Boot roms are 256 bytes, anything past that is ignored and a short one is padded with 0 */
+pub fn new(bytes: &[u8]) -> BootRom {
     let mut m = [0; 256];
-    f.read(&mut m).unwrap();
+    let n = bytes.len().min(256);
+    m[..n].copy_from_slice(&bytes[..n]);
     BootRom{ storage: m }
 }
 
@@ -1,34 +1,124 @@
-use std::fs::File;
-use std::io::Read;
-
 use device::Device;
+use device::cartridge_header::CartridgeHeader;
+use device::cartridge_header::Mapper;
+use device::mbc;
+use device::mbc::mbc1;
+use device::mbc::mbc2;
+use device::mbc::mbc3;
+use device::mbc::mbc5;
+use device::mbc::rom_only;
+use device::mbc::rtc::Rtc;
 
+/* The cartridge owns the game rom and any ram on the cartridge. All access goes through the
+ * memory bank controller for the cartridge type, which decides which banks are visible.
+ */
 pub struct Cartridge {
-    storage: [u8; 32767],
+    header: CartridgeHeader,
+    controller: Box<dyn mbc::Controller>,
+    ram_dirty: bool,
 }
 
 impl Device for Cartridge {
     fn get(&self, a: u16) -> u8 {
-        self.storage[a as usize]
+        self.controller.get(a)
     }
 
     fn set(&mut self, a: u16, v: u8) {
-        self.storage[a as usize] = v;
+        self.controller.set(a, v)
     }
 }
 
 impl Cartridge {
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    /* `a` is relative to the start of cartridge ram at 0xA000 */
+    pub fn get_ram(&self, a: u16) -> u8 {
+        self.controller.get_ram(a)
+    }
+
+    pub fn set_ram(&mut self, a: u16, v: u8) {
+        if self.controller.set_ram(a, v) {
+            self.ram_dirty = true;
+        }
+    }
+
+    /* Only battery backed ram survives power off, so only those cartridges get save files */
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.battery
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        self.controller.ram()
+    }
+
+    pub fn load_ram(&mut self, data: &[u8]) {
+        self.controller.load_ram(data)
+    }
+
+    /* True if ram has been written since the last call */
+    pub fn take_ram_dirty(&mut self) -> bool {
+        let dirty = self.ram_dirty;
+        self.ram_dirty = false;
+        dirty
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.controller.rtc()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.controller.rtc_mut()
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.controller.tick(cycles)
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.controller.rumble()
+    }
 }
 
-pub fn new(game_rom: &mut File) -> Cartridge {
-    let mut m = [0; 32767];
-    game_rom.read(&mut m).unwrap();
-    Cartridge {
-        storage: m,
+/* Builds the cartridge described by `header` around `rom`, picking the memory bank controller
+ * from the cartridge type. Cartridge types we don't emulate are an error rather than something
+ * we'd run and get garbage from.
+ */
+pub fn new(header: CartridgeHeader, mut rom: Vec<u8>) -> Result<Cartridge, String> {
+    /* Pad out truncated roms so bank switching into the missing part reads open bus */
+    if rom.len() < header.rom_size {
+        rom.resize(header.rom_size, 0xFF);
     }
+
+    let t = header.cartridge_type;
+    let ram = if t.ram { header.ram_size } else { 0 };
+
+    let controller: Box<dyn mbc::Controller> = match t.mapper {
+        Mapper::RomOnly => Box::new(rom_only::new(rom, ram)),
+        Mapper::MBC1 => Box::new(mbc1::new(rom, ram)),
+        Mapper::MBC2 => Box::new(mbc2::new(rom)),
+        Mapper::MBC3 => Box::new(mbc3::new(rom, ram, t.timer)),
+        Mapper::MBC5 => Box::new(mbc5::new(rom, ram, t.rumble)),
+        _ => {
+            return Err(format!(
+                "Unsupported cartridge type {:02X} ({:?}) in \"{}\"",
+                t.code, t.mapper, header.title
+            ))
+        }
+    };
+
+    Ok(Cartridge {
+        header: header,
+        controller: controller,
+        ram_dirty: false,
+    })
 }
 
 #[cfg(test)]
 pub fn zero() -> Cartridge {
-    Cartridge{ storage: [0;32767] }
+    use device::cartridge_header;
+
+    let rom = vec![0; 0x8000];
+    new(cartridge_header::parse(&rom).unwrap(), rom).unwrap()
 }
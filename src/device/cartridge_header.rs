@@ -0,0 +1,309 @@
+use std::fmt;
+
+/* The cartridge header lives at 0x0100-0x014F of every rom.
+ * http://gbdev.gg8.se/wiki/articles/The_Cartridge_Header
+ *
+ * 0x0104-0x0133: Nintendo logo, the boot rom refuses to start a cartridge where this doesn't match
+ * 0x0134-0x0143: Title, upper case ascii padded with 0s. Later cartridges shortened it to make
+ *                room for the manufacturer code and cgb flag.
+ * 0x013F-0x0142: Manufacturer code
+ * 0x0143:        CGB flag
+ * 0x0146:        SGB flag
+ * 0x0147:        Cartridge type, which memory bank controller and extra hardware is present
+ * 0x0148:        Rom size
+ * 0x0149:        Ram size
+ * 0x014D:        Header checksum over 0x0134-0x014C
+ * 0x014E-0x014F: Global checksum over the whole rom (excluding these two bytes), big endian
+ */
+
+pub static NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapper {
+    RomOnly,
+    MBC1,
+    MBC2,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    MMM01,
+    PocketCamera,
+    Tama5,
+    HuC1,
+    HuC3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+fn cartridge_type(code: u8) -> Result<CartridgeType, String> {
+    let (mapper, ram, battery, timer, rumble) = match code {
+        0x00 => (Mapper::RomOnly, false, false, false, false),
+        0x01 => (Mapper::MBC1, false, false, false, false),
+        0x02 => (Mapper::MBC1, true, false, false, false),
+        0x03 => (Mapper::MBC1, true, true, false, false),
+        0x05 => (Mapper::MBC2, false, false, false, false),
+        0x06 => (Mapper::MBC2, false, true, false, false),
+        0x08 => (Mapper::RomOnly, true, false, false, false),
+        0x09 => (Mapper::RomOnly, true, true, false, false),
+        0x0B => (Mapper::MMM01, false, false, false, false),
+        0x0C => (Mapper::MMM01, true, false, false, false),
+        0x0D => (Mapper::MMM01, true, true, false, false),
+        0x0F => (Mapper::MBC3, false, true, true, false),
+        0x10 => (Mapper::MBC3, true, true, true, false),
+        0x11 => (Mapper::MBC3, false, false, false, false),
+        0x12 => (Mapper::MBC3, true, false, false, false),
+        0x13 => (Mapper::MBC3, true, true, false, false),
+        0x19 => (Mapper::MBC5, false, false, false, false),
+        0x1A => (Mapper::MBC5, true, false, false, false),
+        0x1B => (Mapper::MBC5, true, true, false, false),
+        0x1C => (Mapper::MBC5, false, false, false, true),
+        0x1D => (Mapper::MBC5, true, false, false, true),
+        0x1E => (Mapper::MBC5, true, true, false, true),
+        0x20 => (Mapper::MBC6, true, true, false, false),
+        0x22 => (Mapper::MBC7, true, true, false, true),
+        0xFC => (Mapper::PocketCamera, true, true, false, false),
+        0xFD => (Mapper::Tama5, true, true, true, false),
+        0xFE => (Mapper::HuC3, true, true, true, false),
+        0xFF => (Mapper::HuC1, true, true, false, false),
+        _ => return Err(format!("Unknown cartridge type: {:02X}", code)),
+    };
+
+    Ok(CartridgeType {
+        code: code,
+        mapper: mapper,
+        ram: ram,
+        battery: battery,
+        timer: timer,
+        rumble: rumble,
+    })
+}
+
+fn rom_size(code: u8) -> Result<usize, String> {
+    match code {
+        0x00..=0x08 => Ok(0x8000 << code),
+        0x52 => Ok(72 * 0x4000),
+        0x53 => Ok(80 * 0x4000),
+        0x54 => Ok(96 * 0x4000),
+        _ => Err(format!("Unknown rom size: {:02X}", code)),
+    }
+}
+
+fn ram_size(code: u8) -> Result<usize, String> {
+    match code {
+        0x00 => Ok(0),
+        0x01 => Ok(0x800),
+        0x02 => Ok(0x2000),
+        0x03 => Ok(0x8000),
+        0x04 => Ok(0x20000),
+        0x05 => Ok(0x10000),
+        _ => Err(format!("Unknown ram size: {:02X}", code)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    None,
+    Supported,
+    Only,
+}
+
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    pub logo: [u8; 48],
+    pub title: String,
+    pub manufacturer_code: String,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub header_checksum_valid: bool,
+    pub global_checksum_valid: bool,
+}
+
+impl CartridgeHeader {
+    pub fn logo_valid(&self) -> bool {
+        self.logo[..] == NINTENDO_LOGO[..]
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:        {}", self.title)?;
+        if !self.manufacturer_code.is_empty() {
+            writeln!(f, "Manufacturer: {}", self.manufacturer_code)?;
+        }
+        writeln!(f, "CGB:          {:?}", self.cgb)?;
+        writeln!(f, "SGB:          {}", self.sgb)?;
+        writeln!(
+            f,
+            "Type:         {:02X} {:?}{}{}{}{}",
+            self.cartridge_type.code,
+            self.cartridge_type.mapper,
+            if self.cartridge_type.ram { "+RAM" } else { "" },
+            if self.cartridge_type.battery { "+BATTERY" } else { "" },
+            if self.cartridge_type.timer { "+TIMER" } else { "" },
+            if self.cartridge_type.rumble { "+RUMBLE" } else { "" },
+        )?;
+        writeln!(f, "Rom size:     {}KiB", self.rom_size / 1024)?;
+        writeln!(f, "Ram size:     {}KiB", self.ram_size / 1024)?;
+        writeln!(f, "Logo:         {}", if self.logo_valid() { "ok" } else { "invalid" })?;
+        writeln!(
+            f,
+            "Checksums:    header {:02X} ({}) global {:04X} ({})",
+            self.header_checksum,
+            if self.header_checksum_valid { "ok" } else { "invalid" },
+            self.global_checksum,
+            if self.global_checksum_valid { "ok" } else { "invalid" },
+        )
+    }
+}
+
+/* Header text fields are padded with 0s and aren't guaranteed to be ascii */
+fn read_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..0x014D]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+
+fn compute_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 0x014E && i != 0x014F)
+        .fold(0u16, |x, (_, b)| x.wrapping_add(*b as u16))
+}
+
+pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, String> {
+    if rom.len() < 0x0150 {
+        return Err(format!(
+            "Rom is too small to contain a cartridge header: {} bytes",
+            rom.len()
+        ));
+    }
+
+    let mut logo = [0; 48];
+    logo.copy_from_slice(&rom[0x0104..0x0134]);
+
+    let cgb = match rom[0x0143] {
+        0x80 => CgbSupport::Supported,
+        0xC0 => CgbSupport::Only,
+        _ => CgbSupport::None,
+    };
+
+    /* Older cartridges use all 16 bytes for the title, cgb era cartridges can only use 15 and
+     * if the manufacturer code is present the title is cut down to 11.
+     */
+    let manufacturer = &rom[0x013F..0x0143];
+    let has_manufacturer = cgb != CgbSupport::None
+        && manufacturer.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit());
+
+    let (title, manufacturer_code) = match (cgb, has_manufacturer) {
+        (CgbSupport::None, _) => (read_string(&rom[0x0134..0x0144]), String::new()),
+        (_, false) => (read_string(&rom[0x0134..0x0143]), String::new()),
+        (_, true) => (read_string(&rom[0x0134..0x013F]), read_string(manufacturer)),
+    };
+
+    let header_checksum = rom[0x014D];
+    let global_checksum = ((rom[0x014E] as u16) << 8) | rom[0x014F] as u16;
+
+    Ok(CartridgeHeader {
+        logo: logo,
+        title: title,
+        manufacturer_code: manufacturer_code,
+        cgb: cgb,
+        sgb: rom[0x0146] == 0x03,
+        cartridge_type: cartridge_type(rom[0x0147])?,
+        rom_size: rom_size(rom[0x0148])?,
+        ram_size: ram_size(rom[0x0149])?,
+        header_checksum: header_checksum,
+        global_checksum: global_checksum,
+        header_checksum_valid: compute_header_checksum(rom) == header_checksum,
+        global_checksum_valid: compute_global_checksum(rom) == global_checksum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(title: &[u8], cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0147] = cartridge_type;
+        rom[0x0148] = rom_size;
+        rom[0x0149] = ram_size;
+        rom[0x014D] = compute_header_checksum(&rom);
+
+        let global = compute_global_checksum(&rom);
+        rom[0x014E] = (global >> 8) as u8;
+        rom[0x014F] = global as u8;
+        rom
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let rom = rom_with_header(b"TETRIS", 0x13, 0x05, 0x03);
+        let header = parse(&rom).unwrap();
+
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.cartridge_type.mapper, Mapper::MBC3);
+        assert_eq!(header.cartridge_type.battery, true);
+        assert_eq!(header.cartridge_type.timer, false);
+        assert_eq!(header.rom_size, 1024 * 1024);
+        assert_eq!(header.ram_size, 32 * 1024);
+        assert_eq!(header.logo_valid(), true);
+        assert_eq!(header.header_checksum_valid, true);
+        assert_eq!(header.global_checksum_valid, true);
+    }
+
+    #[test]
+    fn test_cgb_title_and_manufacturer() {
+        let mut rom = rom_with_header(b"POKEMON YEL", 0x1B, 0x05, 0x03);
+        rom[0x013F..0x0143].copy_from_slice(b"APSE");
+        rom[0x0143] = 0x80;
+
+        let header = parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON YEL");
+        assert_eq!(header.manufacturer_code, "APSE");
+        assert_eq!(header.cgb, CgbSupport::Supported);
+        assert_eq!(header.header_checksum_valid, false);
+    }
+
+    #[test]
+    fn test_unknown_type_is_an_error() {
+        let rom = rom_with_header(b"BROKEN", 0x42, 0x00, 0x00);
+        assert!(parse(&rom).is_err());
+    }
+
+    #[test]
+    fn test_short_rom_is_an_error() {
+        assert!(parse(&[0; 0x100]).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use bytes;
 use device::Device;
 use palette;
+use device::apu;
 use device::interrupt::Interrupt;
-use std::io::{self, Write};
+use device::joypad;
+use device::serial;
+use device::timer;
 
 pub enum LCDControlFlag {
     LCDDisplayEnable,
@@ -57,13 +60,24 @@ pub enum LCDModes {
     Transfer,
 }
 
+impl LCDModes {
+    /* the value of the mode in the bottom two bits of STAT */
+    pub fn get_bits(&self) -> u8 {
+        match self {
+            LCDModes::HBlank => 0,
+            LCDModes::VBlank => 1,
+            LCDModes::Searching => 2,
+            LCDModes::Transfer => 3,
+        }
+    }
+}
+
 pub enum LCDStatusFlag {
     LYCoincidence,
     OAM,
     VBlank,
     HBlank,
     Coincidence,
-    Mode(LCDModes),
 }
 
 impl LCDStatusFlag {
@@ -74,10 +88,6 @@ impl LCDStatusFlag {
             LCDStatusFlag::VBlank => 4,
             LCDStatusFlag::HBlank => 3,
             LCDStatusFlag::Coincidence => 2,
-            LCDStatusFlag::Mode(LCDModes::HBlank) => 1,
-            LCDStatusFlag::Mode(LCDModes::VBlank) => 1,
-            LCDStatusFlag::Mode(LCDModes::Searching) => 0,
-            LCDStatusFlag::Mode(LCDModes::Transfer) => 0,
         }
     }
 }
@@ -100,12 +110,20 @@ impl LCDStatusRegister {
         bytes::check_bit(self.storage, i)
     }
 
+    pub fn set_mode(&mut self, mode: LCDModes) {
+        self.storage = (self.storage & 0xFC) | mode.get_bits();
+    }
+
+    /* bit 7 is unused and always reads back as 1 */
     pub fn get(&self) -> u8 {
-        self.storage
+        self.storage | 0x80
     }
 
+    /* Only the interrupt selects (bits 3-6) are writable, the mode and coincidence bits belong
+     * to the PPU.
+     */
     pub fn set(&mut self, v: u8) {
-        self.storage = v
+        self.storage = (self.storage & 0x07) | (v & 0x78);
     }
 }
 
@@ -135,9 +153,14 @@ impl LCDLineCount {
 
 pub struct HardwareIO {
     pub interrupts: Interrupt,
+    pub joypad: joypad::Joypad,
+    pub serial: serial::Serial,
+    pub timer: timer::Timer,
+    pub apu: apu::APU,
     pub lcd_control_register: LCDControlRegister,
     pub lcd_status_register: LCDStatusRegister,
     pub lcd_line_count: LCDLineCount,
+    pub lcd_line_compare: u8,
     pub background_palette: palette::Palette,
     pub object_palette_1: palette::Palette,
     pub object_palette_2: palette::Palette,
@@ -151,9 +174,14 @@ pub struct HardwareIO {
 pub fn new() -> HardwareIO {
     HardwareIO {
         interrupts: Interrupt { storage: 0 },
+        joypad: joypad::new(),
+        serial: serial::new(),
+        timer: timer::new(),
+        apu: apu::new(),
         lcd_control_register: LCDControlRegister { storage: 0 },
         lcd_status_register: LCDStatusRegister { storage: 0 },
         lcd_line_count: LCDLineCount { storage: 0 },
+        lcd_line_compare: 0,
         background_palette: palette::new(),
         object_palette_1: palette::new(),
         object_palette_2: palette::new(),
@@ -168,14 +196,31 @@ pub fn new() -> HardwareIO {
     }
 }
 
+impl HardwareIO {
+    /* Runs the devices that count cycles on their own forward by `cycles`. */
+    pub fn tick(&mut self, cycles: u32) {
+        self.timer.tick(cycles, &mut self.interrupts);
+        let frame_sequencer_clocks = self.timer.take_frame_sequencer_clocks();
+        self.apu.tick(cycles, frame_sequencer_clocks);
+        self.serial.tick(cycles, &mut self.interrupts);
+    }
+}
+
 impl Device for HardwareIO {
     fn get(&self, a: u16) -> u8 {
         match a {
+            0x0000 => self.joypad.get(),
+            0x0001..=0x0002 => self.serial.get(a - 0x0001),
+            0x0004..=0x0007 => self.timer.get(a - 0x0004),
+            /* the top 3 bits of IF aren't wired up and read as 1 */
+            0x000F => self.interrupts.get() | 0xE0,
+            0x0010..=0x003F => self.apu.get(a - 0x0010),
             0x0040 => self.lcd_control_register.get(),
             0x0041 => self.lcd_status_register.get(),
             0x0042 => self.lcd_scroll_position_y,
             0x0043 => self.lcd_scroll_position_x,
             0x0044 => self.lcd_line_count.get(),
+            0x0045 => self.lcd_line_compare,
             0x0047 => self.background_palette.get(),
             0x0048 => self.object_palette_1.get(),
             0x0049 => self.object_palette_2.get(),
@@ -187,21 +232,13 @@ impl Device for HardwareIO {
 
     fn set(&mut self, a: u16, v: u8) {
         match a {
-            0x0001 => {
-                self.storage[a as usize] = v;
-            }
-            0x0002 => {
-                /* This is the serial output device
-                 * if v is 0x81 then we want to print the buffer
-                 */
-                if v == 0x81 {
-                    print!("{}", self.get(0x0001) as char);
-                    io::stdout().flush().unwrap();
-                }
-            }
+            0x0000 => self.joypad.set(v, &mut self.interrupts),
+            0x0001..=0x0002 => self.serial.set(a - 0x0001, v),
+            0x0004..=0x0007 => self.timer.set(a - 0x0004, v),
             0x000F => {
                 self.interrupts.set(v);
             }
+            0x0010..=0x003F => self.apu.set(a - 0x0010, v),
             0x0040 => self.lcd_control_register.set(v),
             0x0041 => self.lcd_status_register.set(v),
             0x0042 => {
@@ -209,6 +246,7 @@ impl Device for HardwareIO {
             }
             0x0043 => self.lcd_scroll_position_x = v,
             0x0044 => panic!("lcd line count is R/O"),
+            0x0045 => self.lcd_line_compare = v,
             0x0047 => self.background_palette.set(v),
             0x0048 => self.object_palette_1.set(v),
             0x0049 => self.object_palette_2.set(v),
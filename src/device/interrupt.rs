@@ -1,7 +1,7 @@
+use bus::Bus;
 use device::Device;
 use registers::Registers;
 use registers::Registers16;
-use mmu::MMU;
 use instructions;
 
 pub struct Interrupt {
@@ -9,28 +9,38 @@ pub struct Interrupt {
 }
 
 impl Interrupt {
+    pub fn get(&self) -> u8 {
+        self.storage
+    }
+
     pub fn set(&mut self, v: u8) {
-        self.storage = v;
+        self.storage = v & 0x1F;
     }
 
-    pub fn get_interrupts(&self, enabled:u8) -> Vec<Flag> {
-        let masked = enabled & self.storage;
+    pub fn request(&mut self, f: Flag) {
+        self.storage |= 1 << f.get_index();
+    }
 
-        let mut flags = vec![];
+    /* The requested and enabled interrupt with the highest priority, lower bits come first */
+    pub fn get_interrupt(&self, enabled:u8) -> Option<Flag> {
+        let masked = enabled & self.storage;
 
-        for i in 0..5 {
-            if masked & (1 << i) != 0 {
-                flags.push(FLAG_LOOKUP[i]);
-            }
-        }
+        (0..5).find(|i| masked & (1 << i) != 0).map(|i| FLAG_LOOKUP[i])
+    }
 
-        flags
+    pub fn acknowledge(&mut self, f: Flag) {
+        self.storage &= !(1 << f.get_index());
     }
 }
 
-pub fn handle_interrupt(registers:&mut Registers, mmu:&mut MMU, f:Flag) {
-    instructions::push(registers, mmu, &Registers16::PC);
-    println!("Handling Iterrupt: {:?}", f);
+/* Dispatching an interrupt clears its request, pushes PC and jumps to its vector. It takes 5
+ * M-cycles, two idle, two for the push and one to set PC.
+ */
+pub fn handle_interrupt(registers:&mut Registers, bus:&mut Bus, f:Flag) {
+    bus.mmu.hardware_io.interrupts.acknowledge(f);
+    bus.idle();
+    bus.idle();
+    instructions::push(registers, bus, &Registers16::PC);
 
     match f {
         Flag::VBlank => {
@@ -50,6 +60,7 @@ pub fn handle_interrupt(registers:&mut Registers, mmu:&mut MMU, f:Flag) {
         },
     }
 
+    bus.idle();
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -61,6 +72,18 @@ pub enum Flag {
     Joypad,
 }
 
+impl Flag {
+    pub fn get_index(&self) -> u8 {
+        match self {
+            Flag::VBlank => 0,
+            Flag::LCDStat => 1,
+            Flag::Timer => 2,
+            Flag::Serial => 3,
+            Flag::Joypad => 4,
+        }
+    }
+}
+
 pub static FLAG_LOOKUP: [Flag; 5] = [
     Flag::VBlank,
     Flag::LCDStat,
@@ -0,0 +1,160 @@
+use device::interrupt::{Flag, Interrupt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /* The buttons are wired as a 2x4 matrix, these are the input line and which select line
+     * (P14 for the d-pad, P15 for the buttons) it's on.
+     */
+    fn get_line(&self) -> (u8, bool) {
+        match self {
+            Button::Right => (0, false),
+            Button::Left => (1, false),
+            Button::Up => (2, false),
+            Button::Down => (3, false),
+            Button::A => (0, true),
+            Button::B => (1, true),
+            Button::Select => (2, true),
+            Button::Start => (3, true),
+        }
+    }
+}
+
+/* The joypad register at 0xFF00:
+ *
+ * Bit 5: P15 Select buttons    (0=Select)
+ * Bit 4: P14 Select d-pad      (0=Select)
+ * Bit 3: P13 Down or Start     (0=Pressed) (Read Only)
+ * Bit 2: P12 Up or Select      (0=Pressed) (Read Only)
+ * Bit 1: P11 Left or B         (0=Pressed) (Read Only)
+ * Bit 0: P10 Right or A        (0=Pressed) (Read Only)
+ *
+ * The input lines are pulled high and a pressed button connects its line to whichever select
+ * line it's on, so with both groups selected the lines read the buttons ORed together. Any line
+ * going from high to low requests the Joypad interrupt, whether that's from a press or from
+ * selecting a group with a button already held down.
+ */
+pub struct Joypad {
+    select: u8,
+    directions: u8,
+    buttons: u8,
+}
+
+impl Joypad {
+    /* the input lines as the cpu sees them, 1 for released */
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+
+        if self.select & 0x10 == 0 {
+            pressed |= self.directions;
+        }
+
+        if self.select & 0x20 == 0 {
+            pressed |= self.buttons;
+        }
+
+        !pressed & 0x0F
+    }
+
+    fn update(&mut self, before: u8, interrupts: &mut Interrupt) {
+        if before & !self.lines() != 0 {
+            interrupts.request(Flag::Joypad);
+        }
+    }
+
+    pub fn get(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    pub fn set(&mut self, v: u8, interrupts: &mut Interrupt) {
+        let before = self.lines();
+        self.select = v & 0x30;
+        self.update(before, interrupts);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool, interrupts: &mut Interrupt) {
+        let before = self.lines();
+        let (line, is_button) = button.get_line();
+
+        let group = if is_button {
+            &mut self.buttons
+        } else {
+            &mut self.directions
+        };
+
+        if pressed {
+            *group |= 1 << line;
+        } else {
+            *group &= !(1 << line);
+        }
+
+        self.update(before, interrupts);
+    }
+}
+
+pub fn new() -> Joypad {
+    Joypad {
+        select: 0x30,
+        directions: 0,
+        buttons: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_lines() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut joypad = new();
+
+        joypad.set_button(Button::Start, true, &mut interrupts);
+        joypad.set_button(Button::Left, true, &mut interrupts);
+
+        // nothing selected, nothing reads as pressed
+        assert_eq!(joypad.get(), 0xFF);
+        assert_eq!(interrupts.get(), 0x00);
+
+        joypad.set(0x20, &mut interrupts);
+        assert_eq!(joypad.get(), 0xE0 | 0x0D);
+
+        joypad.set(0x10, &mut interrupts);
+        assert_eq!(joypad.get(), 0xD0 | 0x07);
+
+        joypad.set_button(Button::Start, false, &mut interrupts);
+        assert_eq!(joypad.get(), 0xD0 | 0x0F);
+    }
+
+    #[test]
+    fn test_interrupt_on_high_to_low() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut joypad = new();
+
+        joypad.set(0x20, &mut interrupts);
+        joypad.set_button(Button::Up, true, &mut interrupts);
+        assert_eq!(interrupts.get(), 0x10);
+
+        // releasing is a low to high transition
+        interrupts.set(0);
+        joypad.set_button(Button::Up, false, &mut interrupts);
+        assert_eq!(interrupts.get(), 0x00);
+
+        // selecting a group with a button held pulls its line low
+        joypad.set(0x30, &mut interrupts);
+        joypad.set_button(Button::A, true, &mut interrupts);
+        assert_eq!(interrupts.get(), 0x00);
+        joypad.set(0x10, &mut interrupts);
+        assert_eq!(interrupts.get(), 0x10);
+    }
+}
@@ -0,0 +1,194 @@
+use device::Device;
+use device::mbc;
+use device::mbc::Controller;
+
+/* MBC1 supports up to 2MiB of rom and 32KiB of ram, it has four write only registers mapped over
+ * the rom.
+ *
+ * 0x0000-0x1FFF: Ram enable, writing a value with 0xA in the lower nibble enables ram, anything
+ *                else disables it.
+ * 0x2000-0x3FFF: The lower 5 bits of the rom bank mapped into 0x4000-0x7FFF. Writing 0 selects
+ *                bank 1, which means banks 0x20, 0x40 and 0x60 can never be mapped there.
+ * 0x4000-0x5FFF: A 2 bit register, either the ram bank or the upper two bits of the rom bank.
+ * 0x6000-0x7FFF: Banking mode select, in the default mode the 2 bit register only affects
+ *                0x4000-0x7FFF. In advanced mode it also selects the ram bank and the bank mapped
+ *                into 0x0000-0x3FFF.
+ */
+#[derive(Debug, PartialEq)]
+pub enum BankingMode {
+    Simple,
+    Advanced,
+}
+
+pub struct MBC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    bank_2: u8,
+    mode: BankingMode,
+}
+
+impl MBC1 {
+    fn rom_bank_0(&self) -> usize {
+        match self.mode {
+            BankingMode::Simple => 0,
+            BankingMode::Advanced => (self.bank_2 as usize) << 5,
+        }
+    }
+
+    fn rom_bank_1(&self) -> usize {
+        ((self.bank_2 as usize) << 5) | self.rom_bank as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        match self.mode {
+            BankingMode::Simple => 0,
+            BankingMode::Advanced => self.bank_2 as usize,
+        }
+    }
+}
+
+impl Device for MBC1 {
+    fn get(&self, a: u16) -> u8 {
+        let bank = if a < 0x4000 {
+            self.rom_bank_0()
+        } else {
+            self.rom_bank_1()
+        };
+
+        match self.rom.get(mbc::rom_offset(&self.rom, bank, a)) {
+            Some(v) => *v,
+            None => 0xFF,
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0x0000..=0x1FFF => self.ram_enabled = v & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                let bank = v & 0x1F;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.bank_2 = v & 0x03,
+            0x6000..=0x7FFF => {
+                self.mode = if v & 0x01 == 0 {
+                    BankingMode::Simple
+                } else {
+                    BankingMode::Advanced
+                }
+            }
+            _ => panic!("MBC1 write outside of rom address space: {:X}", a),
+        }
+    }
+}
+
+impl Controller for MBC1 {
+    fn get_ram(&self, a: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[mbc::ram_offset(&self.ram, self.ram_bank(), a)]
+    }
+
+    fn set_ram(&mut self, a: u16, v: u8) -> bool {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return false;
+        }
+
+        let offset = mbc::ram_offset(&self.ram, self.ram_bank(), a);
+        self.ram[offset] = v;
+        true
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        mbc::copy_ram(&mut self.ram, data);
+    }
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize) -> MBC1 {
+    MBC1 {
+        rom: rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        rom_bank: 1,
+        bank_2: 0,
+        mode: BankingMode::Simple,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Builds a rom where the first byte of every bank is the bank number */
+    fn banked_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * 0x4000];
+        for b in 0..banks {
+            rom[b * 0x4000] = b as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_rom_bank_switching() {
+        let mut mbc = new(banked_rom(64), 0);
+
+        assert_eq!(mbc.get(0x4000), 1);
+
+        mbc.set(0x2000, 0x05);
+        assert_eq!(mbc.get(0x4000), 5);
+
+        // bank 0 is translated to bank 1
+        mbc.set(0x2000, 0x00);
+        assert_eq!(mbc.get(0x4000), 1);
+
+        // upper bits come from the second register
+        mbc.set(0x2000, 0x02);
+        mbc.set(0x4000, 0x01);
+        assert_eq!(mbc.get(0x4000), 0x22);
+        assert_eq!(mbc.get(0x0000), 0);
+
+        // in advanced mode the second register also applies to 0x0000-0x3FFF
+        mbc.set(0x6000, 0x01);
+        assert_eq!(mbc.get(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_rom_bank_wraps() {
+        let mut mbc = new(banked_rom(4), 0);
+
+        mbc.set(0x2000, 0x06);
+        assert_eq!(mbc.get(0x4000), 2);
+    }
+
+    #[test]
+    fn test_ram_enable_and_banking() {
+        let mut mbc = new(banked_rom(4), 0x8000);
+
+        assert!(!mbc.set_ram(0x0000, 0x12));
+        assert_eq!(mbc.get_ram(0x0000), 0xFF);
+
+        mbc.set(0x0000, 0x0A);
+        assert!(mbc.set_ram(0x0000, 0x12));
+        assert_eq!(mbc.get_ram(0x0000), 0x12);
+
+        // ram banking only applies in advanced mode
+        mbc.set(0x4000, 0x02);
+        assert_eq!(mbc.get_ram(0x0000), 0x12);
+        mbc.set(0x6000, 0x01);
+        assert_eq!(mbc.get_ram(0x0000), 0x00);
+        mbc.set_ram(0x0000, 0x34);
+
+        mbc.set(0x4000, 0x00);
+        assert_eq!(mbc.get_ram(0x0000), 0x12);
+
+        mbc.set(0x0000, 0x00);
+        assert_eq!(mbc.get_ram(0x0000), 0xFF);
+    }
+}
@@ -0,0 +1,121 @@
+use device::Device;
+use device::mbc;
+use device::mbc::Controller;
+
+/* MBC2 supports up to 256KiB of rom and has 512 half bytes of ram built into the controller.
+ *
+ * 0x0000-0x3FFF: A single register selected by bit 8 of the address. With bit 8 clear this is
+ *                ram enable (0xA in the lower nibble enables ram), with it set the lower 4 bits
+ *                select the rom bank mapped into 0x4000-0x7FFF and 0 selects bank 1.
+ *
+ * Only the lower 4 bits of each ram byte exist, the upper bits read back as 1. Only the bottom 9
+ * bits of the address are decoded so the 512 bytes are echoed across all of 0xA000-0xBFFF.
+ */
+pub struct MBC2 {
+    rom: Vec<u8>,
+    ram: [u8; 512],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Device for MBC2 {
+    fn get(&self, a: u16) -> u8 {
+        let bank = if a < 0x4000 { 0 } else { self.rom_bank as usize };
+
+        match self.rom.get(mbc::rom_offset(&self.rom, bank, a)) {
+            Some(v) => *v,
+            None => 0xFF,
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0x0000..=0x3FFF => {
+                if a & 0x0100 == 0 {
+                    self.ram_enabled = v & 0x0F == 0x0A;
+                } else {
+                    let bank = v & 0x0F;
+                    self.rom_bank = if bank == 0 { 1 } else { bank };
+                }
+            }
+            0x4000..=0x7FFF => {}
+            _ => panic!("MBC2 write outside of rom address space: {:X}", a),
+        }
+    }
+}
+
+impl Controller for MBC2 {
+    fn get_ram(&self, a: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        0xF0 | self.ram[(a & 0x01FF) as usize]
+    }
+
+    fn set_ram(&mut self, a: u16, v: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        self.ram[(a & 0x01FF) as usize] = v & 0x0F;
+        true
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        mbc::copy_ram(&mut self.ram, data);
+        for v in self.ram.iter_mut() {
+            *v &= 0x0F;
+        }
+    }
+}
+
+pub fn new(rom: Vec<u8>) -> MBC2 {
+    MBC2 {
+        rom: rom,
+        ram: [0; 512],
+        ram_enabled: false,
+        rom_bank: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_select_by_address_bit_8() {
+        let mut rom = vec![0; 16 * 0x4000];
+        rom[3 * 0x4000] = 3;
+        let mut mbc = new(rom);
+
+        // bit 8 clear is ram enable, doesn't touch the rom bank
+        mbc.set(0x0000, 0x03);
+        assert_eq!(mbc.get(0x4000), 0);
+
+        mbc.set(0x2100, 0x03);
+        assert_eq!(mbc.get(0x4000), 3);
+
+        mbc.set(0x0100, 0x0A);
+        assert_eq!(mbc.get_ram(0x0000), 0xFF);
+        assert_eq!(mbc.ram_enabled, false);
+
+        mbc.set(0x0000, 0x0A);
+        assert_eq!(mbc.ram_enabled, true);
+    }
+
+    #[test]
+    fn test_nibble_ram_echoes() {
+        let mut mbc = new(vec![0; 0x8000]);
+        mbc.set(0x0000, 0x0A);
+
+        mbc.set_ram(0x0010, 0xAB);
+        assert_eq!(mbc.get_ram(0x0010), 0xFB);
+        assert_eq!(mbc.get_ram(0x0210), 0xFB);
+        assert_eq!(mbc.get_ram(0x1E10), 0xFB);
+    }
+}
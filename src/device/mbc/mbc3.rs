@@ -0,0 +1,165 @@
+use device::Device;
+use device::mbc;
+use device::mbc::Controller;
+use device::mbc::rtc;
+
+/* MBC3 supports up to 2MiB of rom, 32KiB of ram and optionally a real time clock.
+ *
+ * 0x0000-0x1FFF: Ram and timer enable, 0xA in the lower nibble enables both.
+ * 0x2000-0x3FFF: The 7 bit rom bank mapped into 0x4000-0x7FFF, 0 selects bank 1.
+ * 0x4000-0x5FFF: 0x00-0x03 selects a ram bank, 0x08-0x0C maps an rtc register into
+ *                0xA000-0xBFFF instead.
+ * 0x6000-0x7FFF: Latch clock data, see `rtc`.
+ */
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_select: u8,
+    rtc: Option<rtc::Rtc>,
+}
+
+impl Device for MBC3 {
+    fn get(&self, a: u16) -> u8 {
+        let bank = if a < 0x4000 { 0 } else { self.rom_bank as usize };
+
+        match self.rom.get(mbc::rom_offset(&self.rom, bank, a)) {
+            Some(v) => *v,
+            None => 0xFF,
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0x0000..=0x1FFF => self.ram_enabled = v & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                let bank = v & 0x7F;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.ram_select = v,
+            0x6000..=0x7FFF => {
+                if let Some(ref mut rtc) = self.rtc {
+                    rtc.latch(v);
+                }
+            }
+            _ => panic!("MBC3 write outside of rom address space: {:X}", a),
+        }
+    }
+}
+
+impl Controller for MBC3 {
+    fn get_ram(&self, a: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match self.ram_select {
+            0x00..=0x03 if !self.ram.is_empty() => {
+                self.ram[mbc::ram_offset(&self.ram, self.ram_select as usize, a)]
+            }
+            0x08..=0x0C => match self.rtc {
+                Some(ref rtc) => rtc.get(self.ram_select),
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn set_ram(&mut self, a: u16, v: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        match self.ram_select {
+            0x00..=0x03 if !self.ram.is_empty() => {
+                let offset = mbc::ram_offset(&self.ram, self.ram_select as usize, a);
+                self.ram[offset] = v;
+                true
+            }
+            0x08..=0x0C => {
+                if let Some(ref mut rtc) = self.rtc {
+                    rtc.set(self.ram_select, v);
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        mbc::copy_ram(&mut self.ram, data);
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn rtc(&self) -> Option<&rtc::Rtc> {
+        self.rtc.as_ref()
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut rtc::Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> MBC3 {
+    MBC3 {
+        rom: rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        rom_bank: 1,
+        ram_select: 0,
+        rtc: if has_rtc { Some(rtc::new()) } else { None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_bank_switching() {
+        let mut rom = vec![0; 128 * 0x4000];
+        rom[0x7F * 0x4000] = 0x7F;
+        rom[0x4000] = 0x01;
+
+        let mut mbc = new(rom, 0, false);
+
+        mbc.set(0x2000, 0x7F);
+        assert_eq!(mbc.get(0x4000), 0x7F);
+
+        mbc.set(0x2000, 0x00);
+        assert_eq!(mbc.get(0x4000), 0x01);
+    }
+
+    #[test]
+    fn test_rtc_registers_mapped_over_ram() {
+        let mut mbc = new(vec![0; 0x8000], 0x8000, true);
+        mbc.set(0x0000, 0x0A);
+
+        assert!(mbc.set_ram(0x0000, 0x42));
+
+        // rtc registers aren't ram
+        mbc.set(0x4000, 0x08);
+        assert!(!mbc.set_ram(0x0000, 30));
+        assert_eq!(mbc.get_ram(0x0000), 30);
+
+        mbc.tick(4194304);
+        assert_eq!(mbc.get_ram(0x0000), 30);
+
+        mbc.set(0x6000, 0x00);
+        mbc.set(0x6000, 0x01);
+        assert_eq!(mbc.get_ram(0x0000), 31);
+
+        mbc.set(0x4000, 0x00);
+        assert_eq!(mbc.get_ram(0x0000), 0x42);
+    }
+}
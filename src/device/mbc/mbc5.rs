@@ -0,0 +1,148 @@
+use device::Device;
+use device::mbc;
+use device::mbc::Controller;
+
+/* MBC5 supports up to 8MiB of rom and 128KiB of ram.
+ *
+ * 0x0000-0x1FFF: Ram enable, only exactly 0x0A enables ram.
+ * 0x2000-0x2FFF: Lower 8 bits of the rom bank mapped into 0x4000-0x7FFF. Unlike the earlier
+ *                controllers bank 0 can be mapped there.
+ * 0x3000-0x3FFF: Bit 8 of the rom bank.
+ * 0x4000-0x5FFF: Ram bank 0x00-0x0F. Rumble cartridges wire bit 3 to the motor instead, which
+ *                leaves them with 8 ram banks.
+ */
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Device for MBC5 {
+    fn get(&self, a: u16) -> u8 {
+        let bank = if a < 0x4000 { 0 } else { self.rom_bank as usize };
+
+        match self.rom.get(mbc::rom_offset(&self.rom, bank, a)) {
+            Some(v) => *v,
+            None => 0xFF,
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0x0000..=0x1FFF => self.ram_enabled = v == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | v as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((v as u16 & 0x01) << 8),
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = v & 0x08 != 0;
+                    self.ram_bank = v & 0x07;
+                } else {
+                    self.ram_bank = v & 0x0F;
+                }
+            }
+            0x6000..=0x7FFF => {}
+            _ => panic!("MBC5 write outside of rom address space: {:X}", a),
+        }
+    }
+}
+
+impl Controller for MBC5 {
+    fn get_ram(&self, a: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[mbc::ram_offset(&self.ram, self.ram_bank as usize, a)]
+    }
+
+    fn set_ram(&mut self, a: u16, v: u8) -> bool {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return false;
+        }
+
+        let offset = mbc::ram_offset(&self.ram, self.ram_bank as usize, a);
+        self.ram[offset] = v;
+        true
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        mbc::copy_ram(&mut self.ram, data);
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> MBC5 {
+    MBC5 {
+        rom: rom,
+        ram: vec![0; ram_size],
+        ram_enabled: false,
+        rom_bank: 1,
+        ram_bank: 0,
+        has_rumble: has_rumble,
+        rumble: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nine_bit_rom_bank() {
+        let mut rom = vec![0; 512 * 0x4000];
+        rom[0x1FF * 0x4000] = 0xAB;
+        rom[0x100 * 0x4000] = 0xCD;
+        rom[0] = 0xEF;
+
+        let mut mbc = new(rom, 0, false);
+
+        mbc.set(0x2000, 0xFF);
+        mbc.set(0x3000, 0x01);
+        assert_eq!(mbc.get(0x4000), 0xAB);
+
+        mbc.set(0x2000, 0x00);
+        assert_eq!(mbc.get(0x4000), 0xCD);
+
+        // bank 0 can be mapped into the switchable window
+        mbc.set(0x3000, 0x00);
+        assert_eq!(mbc.get(0x4000), 0xEF);
+    }
+
+    #[test]
+    fn test_rumble_motor_bit() {
+        let mut mbc = new(vec![0; 0x8000], 0x20000, true);
+        mbc.set(0x0000, 0x0A);
+
+        mbc.set(0x4000, 0x09);
+        assert_eq!(mbc.rumble(), true);
+
+        // the motor bit doesn't take part in ram bank selection
+        mbc.set_ram(0x0000, 0x12);
+        mbc.set(0x4000, 0x01);
+        assert_eq!(mbc.rumble(), false);
+        assert_eq!(mbc.get_ram(0x0000), 0x12);
+    }
+
+    #[test]
+    fn test_rumble_bit_is_a_ram_bank_without_motor() {
+        let mut mbc = new(vec![0; 0x8000], 0x20000, false);
+        mbc.set(0x0000, 0x0A);
+
+        mbc.set(0x4000, 0x09);
+        mbc.set_ram(0x0000, 0x12);
+        mbc.set(0x4000, 0x01);
+        assert_eq!(mbc.rumble(), false);
+        assert_eq!(mbc.get_ram(0x0000), 0x00);
+    }
+}
@@ -0,0 +1,72 @@
+/* Memory bank controllers sit between the cpu and the rom/ram on the cartridge. The cpu can only
+ * see 32KiB of rom and 8KiB of external ram at a time, so larger cartridges include a controller
+ * that swaps banks of rom and ram in and out of those windows.
+ *
+ * Writes into the rom address space (0x0000-0x7FFF) never reach the rom, they land in the
+ * controller's registers instead. Reads and writes through `Device` use the absolute rom
+ * address, while the ram functions are relative to the start of cartridge ram at 0xA000.
+ */
+use device::Device;
+use device::mbc::rtc::Rtc;
+
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod rom_only;
+pub mod rtc;
+
+pub trait Controller: Device {
+    fn get_ram(&self, a: u16) -> u8;
+
+    /* Returns whether `v` was stored in ram, writes are dropped while ram is disabled and the
+     * MBC3 rtc registers aren't ram.
+     */
+    fn set_ram(&mut self, a: u16, v: u8) -> bool;
+
+    /* The full contents of cartridge ram across all banks, this is what gets written to save
+     * files for battery backed cartridges.
+     */
+    fn ram(&self) -> &[u8];
+    fn load_ram(&mut self, data: &[u8]);
+
+    /* Controllers with their own clock (the MBC3 rtc) are advanced by the cycles the cpu ran */
+    fn tick(&mut self, _cycles: u32) {}
+
+    /* Whether the cartridge's rumble motor is currently switched on */
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    fn rtc(&self) -> Option<&Rtc> {
+        None
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+}
+
+/* Finds the offset into the rom of address `a` (0x0000-0x7FFF) when `bank` is mapped into the
+ * window `a` falls in. Bank numbers wrap around the number of banks the rom actually has, which
+ * is what the real hardware does since it ignores the unconnected high bits.
+ */
+pub fn rom_offset(rom: &Vec<u8>, bank: usize, a: u16) -> usize {
+    let banks = (rom.len() / 0x4000).max(1);
+    ((bank % banks) * 0x4000) + (a as usize & 0x3FFF)
+}
+
+/* Finds the offset into the ram of address `a` (relative to 0xA000) for the selected bank,
+ * cartridges with less than 8KiB of ram see it mirrored across the window.
+ */
+pub fn ram_offset(ram: &Vec<u8>, bank: usize, a: u16) -> usize {
+    ((bank * 0x2000) + a as usize) % ram.len()
+}
+
+/* Copies a saved ram image into cartridge ram, save files from other emulators are sometimes a
+ * different size so copy what overlaps and leave the rest alone.
+ */
+pub fn copy_ram(ram: &mut [u8], data: &[u8]) {
+    let n = ram.len().min(data.len());
+    ram[..n].copy_from_slice(&data[..n]);
+}
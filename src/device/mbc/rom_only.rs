@@ -0,0 +1,57 @@
+use device::Device;
+use device::mbc;
+use device::mbc::Controller;
+
+/* Cartridges with 32KiB of rom or less have no controller, the whole rom is mapped directly and
+ * writes are ignored. A handful of these carry up to 8KiB of ram wired straight to 0xA000.
+ */
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl Device for RomOnly {
+    fn get(&self, a: u16) -> u8 {
+        match self.rom.get(a as usize) {
+            Some(v) => *v,
+            None => 0xFF,
+        }
+    }
+
+    fn set(&mut self, _: u16, _: u8) {}
+}
+
+impl Controller for RomOnly {
+    fn get_ram(&self, a: u16) -> u8 {
+        if self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[mbc::ram_offset(&self.ram, 0, a)]
+    }
+
+    fn set_ram(&mut self, a: u16, v: u8) -> bool {
+        if self.ram.is_empty() {
+            return false;
+        }
+
+        let offset = mbc::ram_offset(&self.ram, 0, a);
+        self.ram[offset] = v;
+        true
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        mbc::copy_ram(&mut self.ram, data);
+    }
+}
+
+pub fn new(rom: Vec<u8>, ram_size: usize) -> RomOnly {
+    RomOnly {
+        rom: rom,
+        ram: vec![0; ram_size],
+    }
+}
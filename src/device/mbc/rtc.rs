@@ -0,0 +1,282 @@
+/* The real time clock found on MBC3 cartridges. It keeps counting while the gameboy is off by
+ * running from the cartridge battery, the registers are:
+ *
+ * 0x08: Seconds    (0-59)
+ * 0x09: Minutes    (0-59)
+ * 0x0A: Hours      (0-23)
+ * 0x0B: Days       lower 8 bits of the 9 bit day counter
+ * 0x0C: Day High   bit 0: day counter bit 8, bit 6: halt, bit 7: day counter carry
+ *
+ * The cpu never reads the counting registers directly, writing 0x00 then 0x01 to 0x6000-0x7FFF
+ * latches the current time into a second set of registers which is what reads return.
+ */
+
+/* The gameboy clock runs at 4194304Hz, so that many cycles make up one second of clock time */
+const CYCLES_PER_SECOND: u32 = 4194304;
+
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    latched: [u8; 5],
+    latch_armed: bool,
+    cycles: u32,
+}
+
+impl Rtc {
+    pub fn get(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    pub fn set(&mut self, register: u8, v: u8) {
+        match register {
+            0x08 => {
+                /* writing the seconds resets the sub second counter */
+                self.seconds = v & 0x3F;
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = v & 0x3F,
+            0x0A => self.hours = v & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | v as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((v as u16 & 0x01) << 8);
+                self.halted = v & 0x40 != 0;
+                self.day_carry = v & 0x80 != 0;
+            }
+            _ => panic!("invalid rtc register: {:X}", register),
+        }
+
+        /* the latched copy is what the cpu reads back, keep it in step with what was written */
+        self.latched = self.registers();
+    }
+
+    /* Writes to 0x6000-0x7FFF, a write of 0x00 followed by 0x01 latches the clock */
+    pub fn latch(&mut self, v: u8) {
+        if self.latch_armed && v == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_armed = v == 0x00;
+    }
+
+    /* The registers as last latched, what reads return */
+    pub fn latched(&self) -> [u8; 5] {
+        self.latched
+    }
+
+    /* Restores a clock from a save file, neither set goes through `set` since that would
+     * overwrite the latched registers.
+     */
+    pub fn restore(&mut self, registers: [u8; 5], latched: [u8; 5]) {
+        self.seconds = registers[0] & 0x3F;
+        self.minutes = registers[1] & 0x3F;
+        self.hours = registers[2] & 0x1F;
+        self.days = registers[3] as u16 | ((registers[4] as u16 & 0x01) << 8);
+        self.halted = registers[4] & 0x40 != 0;
+        self.day_carry = registers[4] & 0x80 != 0;
+        self.latched = latched;
+        self.cycles = 0;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halted {
+            return;
+        }
+
+        self.cycles += cycles;
+
+        if self.cycles >= CYCLES_PER_SECOND {
+            let seconds = self.cycles / CYCLES_PER_SECOND;
+            self.cycles %= CYCLES_PER_SECOND;
+            self.advance_seconds(seconds as u64);
+        }
+    }
+
+    /* Moves the clock forward by `n` seconds, used both for emulated time and for catching up on
+     * time that passed on the host while the emulator wasn't running.
+     */
+    pub fn advance_seconds(&mut self, n: u64) {
+        if self.halted {
+            return;
+        }
+
+        /* Registers can be written with out of range values (a seconds value of 61 for example),
+         * these count up to the register's bit width and wrap to 0 without carrying. Step one
+         * second at a time until everything is back in range then do the rest arithmetically.
+         */
+        let mut remaining = n;
+        while remaining > 0 && !self.in_range() {
+            self.tick_second();
+            remaining -= 1;
+        }
+
+        if remaining == 0 {
+            return;
+        }
+
+        let total = self.seconds as u64
+            + (self.minutes as u64 * 60)
+            + (self.hours as u64 * 3600)
+            + (self.days as u64 * 86400)
+            + remaining;
+
+        let days = total / 86400;
+
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+
+        self.days = (days % 0x200) as u16;
+        self.hours = ((total % 86400) / 3600) as u8;
+        self.minutes = ((total % 3600) / 60) as u8;
+        self.seconds = (total % 60) as u8;
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    /* The counting registers in the order seconds, minutes, hours, days low, days high */
+    pub fn registers(&self) -> [u8; 5] {
+        let mut day_high = (self.days >> 8) as u8;
+        if self.halted {
+            day_high |= 0x40;
+        }
+        if self.day_carry {
+            day_high |= 0x80;
+        }
+
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            day_high,
+        ]
+    }
+}
+
+pub fn new() -> Rtc {
+    Rtc {
+        seconds: 0,
+        minutes: 0,
+        hours: 0,
+        days: 0,
+        halted: false,
+        day_carry: false,
+        latched: [0; 5],
+        latch_armed: false,
+        cycles: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latch(rtc: &mut Rtc) {
+        rtc.latch(0x00);
+        rtc.latch(0x01);
+    }
+
+    #[test]
+    fn test_ticks_from_cycles() {
+        let mut rtc = new();
+
+        rtc.tick(CYCLES_PER_SECOND - 1);
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 0);
+
+        rtc.tick(1);
+        assert_eq!(rtc.get(0x08), 0);
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 1);
+    }
+
+    #[test]
+    fn test_latch_needs_zero_then_one() {
+        let mut rtc = new();
+        rtc.advance_seconds(5);
+
+        rtc.latch(0x01);
+        assert_eq!(rtc.get(0x08), 0);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 5);
+    }
+
+    #[test]
+    fn test_rollover_and_day_carry() {
+        let mut rtc = new();
+        rtc.set(0x0B, 0xFF);
+        rtc.set(0x0C, 0x01);
+        rtc.set(0x0A, 23);
+        rtc.set(0x09, 59);
+        rtc.set(0x08, 59);
+
+        rtc.advance_seconds(1);
+        latch(&mut rtc);
+
+        assert_eq!(rtc.get(0x08), 0);
+        assert_eq!(rtc.get(0x09), 0);
+        assert_eq!(rtc.get(0x0A), 0);
+        assert_eq!(rtc.get(0x0B), 0);
+        assert_eq!(rtc.get(0x0C), 0x80);
+    }
+
+    #[test]
+    fn test_halt_stops_the_clock() {
+        let mut rtc = new();
+        rtc.set(0x0C, 0x40);
+
+        rtc.tick(CYCLES_PER_SECOND * 2);
+        rtc.advance_seconds(100);
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 0);
+    }
+
+    #[test]
+    fn test_out_of_range_values_wrap_without_carry() {
+        let mut rtc = new();
+        rtc.set(0x08, 62);
+
+        rtc.advance_seconds(2);
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 0);
+        assert_eq!(rtc.get(0x09), 0);
+
+        rtc.advance_seconds(3661);
+        latch(&mut rtc);
+        assert_eq!(rtc.get(0x08), 1);
+        assert_eq!(rtc.get(0x09), 1);
+        assert_eq!(rtc.get(0x0A), 1);
+    }
+}
@@ -1,9 +1,17 @@
+pub mod apu;
 pub mod cartridge;
+pub mod cartridge_header;
+pub mod mbc;
 pub mod hardware_io;
 pub mod interrupt;
+pub mod joypad;
 pub mod not_implemented;
+pub mod object_attribute_memory;
 pub mod ram_bank;
+pub mod serial;
+pub mod socket_link;
 pub mod tile_data;
+pub mod timer;
 pub mod tile_map;
 pub mod zero_page;
 pub mod boot_rom;
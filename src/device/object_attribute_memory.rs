@@ -0,0 +1,58 @@
+use device::Device;
+
+/* Object attribute memory holds the 40 sprites, 4 bytes each:
+ *
+ * 0: Y position, offset by 16 so sprites can sit partially off the top of the screen
+ * 1: X position, offset by 8
+ * 2: Tile index, sprites always use the tile data at 0x8000
+ * 3: Flags
+ *      Bit 7: Background priority (0=Above background, 1=Behind background colors 1-3)
+ *      Bit 6: Y flip
+ *      Bit 5: X flip
+ *      Bit 4: Palette (0=OBP0, 1=OBP1)
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub y: i16,
+    pub x: i16,
+    pub tile: u8,
+    pub behind_background: bool,
+    pub y_flip: bool,
+    pub x_flip: bool,
+    pub use_palette_1: bool,
+}
+
+pub struct ObjectAttributeMemory {
+    storage: [u8; 160],
+}
+
+impl Device for ObjectAttributeMemory {
+    fn get(&self, a: u16) -> u8 {
+        self.storage[a as usize]
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        self.storage[a as usize] = v;
+    }
+}
+
+impl ObjectAttributeMemory {
+    pub fn get_sprite(&self, index: u8) -> Sprite {
+        let offset = index as usize * 4;
+        let flags = self.storage[offset + 3];
+
+        Sprite {
+            y: self.storage[offset] as i16 - 16,
+            x: self.storage[offset + 1] as i16 - 8,
+            tile: self.storage[offset + 2],
+            behind_background: flags & 0x80 != 0,
+            y_flip: flags & 0x40 != 0,
+            x_flip: flags & 0x20 != 0,
+            use_palette_1: flags & 0x10 != 0,
+        }
+    }
+}
+
+pub fn new() -> ObjectAttributeMemory {
+    ObjectAttributeMemory { storage: [0; 160] }
+}
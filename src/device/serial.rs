@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use device::Device;
+use device::interrupt::{Flag, Interrupt};
+
+/* The other end of the link cable.
+ *
+ * The gameboy driving the clock (internal clock) calls `transfer` when it starts shifting a byte
+ * out and gets back the byte the other end had loaded. The gameboy on the external clock can't
+ * start anything itself, it calls `poll` every tick with the byte it has ready and completes when
+ * the other end has clocked a transfer.
+ */
+pub trait Link {
+    fn transfer(&mut self, out: u8) -> u8;
+
+    fn poll(&mut self, _out: u8) -> Option<u8> {
+        None
+    }
+
+    /* Called with the cycles the gameboy has run, for links that need to keep time */
+    fn tick(&mut self, _cycles: u32) {}
+}
+
+/* Nothing plugged in, the data line floats high so every transfer reads 0xFF */
+pub struct Disconnected;
+
+impl Link for Disconnected {
+    fn transfer(&mut self, _out: u8) -> u8 {
+        0xFF
+    }
+}
+
+/* Writes every byte sent to stdout, test roms like blargg's report their results this way */
+pub struct Printer;
+
+impl Link for Printer {
+    fn transfer(&mut self, out: u8) -> u8 {
+        print!("{}", out as char);
+        io::stdout().flush().unwrap();
+        0xFF
+    }
+}
+
+/* Keeps every byte sent in a buffer that can be read back while the gameboy is running */
+pub struct Capture {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Capture {
+    pub fn buffer(&self) -> Rc<RefCell<Vec<u8>>> {
+        self.buffer.clone()
+    }
+}
+
+impl Link for Capture {
+    fn transfer(&mut self, out: u8) -> u8 {
+        self.buffer.borrow_mut().push(out);
+        0xFF
+    }
+}
+
+pub fn capture() -> Capture {
+    Capture {
+        buffer: Rc::new(RefCell::new(Vec::new())),
+    }
+}
+
+/* The cable between two gameboys in the same process. For each end it holds the byte that end
+ * has ready while it waits on the external clock, and a byte clocked in from the other end that
+ * it hasn't picked up yet. Only tests link gameboys in process for now.
+ */
+#[cfg(test)]
+struct Wire {
+    ready: [Option<u8>; 2],
+    received: [Option<u8>; 2],
+}
+
+#[cfg(test)]
+pub struct WireEnd {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+#[cfg(test)]
+impl Link for WireEnd {
+    /* if the other end isn't waiting for a transfer nothing gets shifted in */
+    fn transfer(&mut self, out: u8) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+
+        match wire.ready[other].take() {
+            Some(v) => {
+                wire.received[other] = Some(out);
+                v
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn poll(&mut self, out: u8) -> Option<u8> {
+        let mut wire = self.wire.borrow_mut();
+
+        match wire.received[self.side].take() {
+            Some(v) => Some(v),
+            None => {
+                wire.ready[self.side] = Some(out);
+                None
+            }
+        }
+    }
+
+    /* the serial port polls straight after every tick while it's waiting, so until it does this
+     * end isn't ready
+     */
+    fn tick(&mut self, _cycles: u32) {
+        self.wire.borrow_mut().ready[self.side] = None;
+    }
+}
+
+/* Both ends of a link cable for connecting two gameboys in the same process */
+#[cfg(test)]
+pub fn pair() -> (WireEnd, WireEnd) {
+    let wire = Rc::new(RefCell::new(Wire {
+        ready: [None, None],
+        received: [None, None],
+    }));
+
+    (
+        WireEnd { wire: wire.clone(), side: 0 },
+        WireEnd { wire: wire, side: 1 },
+    )
+}
+
+/* The serial registers, addressed relative to 0xFF01:
+ *
+ * 0: SB - Serial transfer data
+ * 1: SC - Bit 7: Transfer start / in progress
+ *         Bit 0: Clock (0=External, 1=Internal)
+ *
+ * With the internal clock the gameboy shifts SB out MSB first at 8192Hz (512 cycles a bit)
+ * while shifting the other end's bits in, so after 8 bits SB holds the byte received. When the
+ * transfer completes bit 7 of SC is cleared and the Serial interrupt is requested.
+ */
+pub struct Serial {
+    data: u8,
+    control: u8,
+    incoming: u8,
+    bits: u8,
+    clock: u32,
+    link: Box<dyn Link>,
+}
+
+impl Device for Serial {
+    fn get(&self, a: u16) -> u8 {
+        match a {
+            0 => self.data,
+            1 => self.control | 0x7E,
+            _ => panic!("serial address out of range: {:X}", a),
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0 => self.data = v,
+            1 => {
+                self.control = v & 0x81;
+                self.bits = 0;
+                self.clock = 0;
+
+                if self.control == 0x81 {
+                    self.incoming = self.link.transfer(self.data);
+                    self.bits = 8;
+                }
+            }
+            _ => panic!("serial address out of range: {:X}", a),
+        }
+    }
+}
+
+impl Serial {
+    pub fn set_link(&mut self, link: Box<dyn Link>) {
+        self.link = link;
+    }
+
+    fn complete(&mut self, interrupts: &mut Interrupt) {
+        self.control &= 0x01;
+        interrupts.request(Flag::Serial);
+    }
+
+    pub fn tick(&mut self, cycles: u32, interrupts: &mut Interrupt) {
+        self.link.tick(cycles);
+
+        if self.control & 0x80 == 0 {
+            return;
+        }
+
+        if self.control & 0x01 == 0 {
+            if let Some(v) = self.link.poll(self.data) {
+                self.data = v;
+                self.complete(interrupts);
+            }
+            return;
+        }
+
+        self.clock += cycles;
+
+        while self.clock >= 512 && self.bits > 0 {
+            self.clock -= 512;
+            self.bits -= 1;
+            self.data = (self.data << 1) | ((self.incoming >> self.bits) & 1);
+        }
+
+        if self.bits == 0 {
+            self.complete(interrupts);
+        }
+    }
+}
+
+pub fn new() -> Serial {
+    Serial {
+        data: 0,
+        control: 0,
+        incoming: 0,
+        bits: 0,
+        clock: 0,
+        link: Box::new(Disconnected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut serial = new();
+        let capture = capture();
+        let buffer = capture.buffer();
+        serial.set_link(Box::new(capture));
+
+        serial.set(0, 0x42);
+        serial.set(1, 0x81);
+        assert_eq!(*buffer.borrow(), vec![0x42]);
+
+        // half way through the other end's bits have been shifted in
+        serial.tick(512 * 4, &mut interrupts);
+        assert_eq!(serial.get(0), 0x2F);
+        assert_eq!(serial.get(1), 0xFF);
+
+        serial.tick(512 * 4 - 4, &mut interrupts);
+        assert_eq!(interrupts.get(), 0x00);
+
+        serial.tick(4, &mut interrupts);
+        assert_eq!(serial.get(0), 0xFF);
+        assert_eq!(serial.get(1), 0x7F);
+        assert_eq!(interrupts.get(), 0x08);
+    }
+
+    #[test]
+    fn test_external_clock_waits_for_the_other_end() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut serial = new();
+
+        serial.set(0, 0x42);
+        serial.set(1, 0x80);
+        serial.tick(512 * 100, &mut interrupts);
+
+        assert_eq!(serial.get(0), 0x42);
+        assert_eq!(serial.get(1), 0xFE);
+        assert_eq!(interrupts.get(), 0x00);
+    }
+}
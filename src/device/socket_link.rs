@@ -0,0 +1,249 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use device::serial::Link;
+
+/* A link cable between two emulator processes over a local socket.
+ *
+ * Addresses are either a tcp `host:port` or `unix:<path>` for a unix domain socket. Messages are
+ * two bytes, a tag and a value:
+ *
+ * SYNC:  the sender has run another quantum of cycles
+ * DATA:  the sender is driving the clock and shifting out value
+ * REPLY: the byte shifted back in response to a DATA
+ *
+ * The two emulators run in lockstep, at the end of every quantum each sends a SYNC and then waits
+ * until the other end is no more than one quantum behind. That keeps them within a couple of
+ * milliseconds of emulated time of each other, close enough that games' link handshakes don't
+ * time out while neither side has to wait on the other for every cycle.
+ */
+const QUANTUM: u32 = 4096;
+
+const SYNC: u8 = 0;
+const DATA: u8 = 1;
+const REPLY: u8 = 2;
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+pub struct SocketLink {
+    stream: Option<Box<dyn Stream>>,
+    cycles: u32,
+    quantum: u64,
+    peer_quantum: u64,
+    /* a byte the other end has clocked out to us that hasn't been picked up yet */
+    pending: Option<u8>,
+}
+
+impl SocketLink {
+    fn disconnect(&mut self, reason: String) {
+        if self.stream.take().is_some() {
+            println!("Link cable disconnected: {}", reason);
+        }
+    }
+
+    fn send(&mut self, tag: u8, v: u8) {
+        let result = match self.stream {
+            Some(ref mut stream) => stream.write_all(&[tag, v]),
+            None => return,
+        };
+
+        if let Err(e) = result {
+            self.disconnect(e.to_string());
+        }
+    }
+
+    fn receive(&mut self) -> Option<(u8, u8)> {
+        let mut message = [0; 2];
+
+        let result = match self.stream {
+            Some(ref mut stream) => stream.read_exact(&mut message),
+            None => return None,
+        };
+
+        match result {
+            Ok(()) if message[0] <= REPLY => Some((message[0], message[1])),
+            Ok(()) => {
+                self.disconnect(format!("unknown message {:02X}", message[0]));
+                None
+            }
+            Err(e) => {
+                self.disconnect(e.to_string());
+                None
+            }
+        }
+    }
+
+    /* A byte that was clocked out to us but that we weren't ready for gets 0xFF back */
+    fn drop_pending(&mut self) {
+        if self.pending.take().is_some() {
+            self.send(REPLY, 0xFF);
+        }
+    }
+}
+
+impl Link for SocketLink {
+    fn transfer(&mut self, out: u8) -> u8 {
+        self.drop_pending();
+        self.send(DATA, out);
+
+        loop {
+            match self.receive() {
+                Some((REPLY, v)) => return v,
+                Some((DATA, _)) => {
+                    /* both ends are driving the clock, neither sees the other's byte */
+                    self.send(REPLY, 0xFF);
+                }
+                Some(_) => self.peer_quantum += 1,
+                None => return 0xFF,
+            }
+        }
+    }
+
+    fn poll(&mut self, out: u8) -> Option<u8> {
+        let v = self.pending.take();
+
+        if v.is_some() {
+            self.send(REPLY, out);
+        }
+
+        v
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        /* the serial port polls straight after ticking the link, if it didn't pick the byte up
+         * it isn't waiting for one
+         */
+        self.drop_pending();
+
+        self.cycles += cycles;
+
+        while self.cycles >= QUANTUM {
+            self.cycles -= QUANTUM;
+            self.quantum += 1;
+            self.send(SYNC, 0);
+
+            /* stop waiting when a transfer comes in, the other end is stuck until we reply */
+            while self.stream.is_some() && self.pending.is_none()
+                && self.peer_quantum + 1 < self.quantum
+            {
+                match self.receive() {
+                    Some((SYNC, _)) => self.peer_quantum += 1,
+                    Some((DATA, v)) => self.pending = Some(v),
+                    Some(_) => self.disconnect(String::from("unexpected reply")),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+fn from_stream(stream: Box<dyn Stream>) -> SocketLink {
+    SocketLink {
+        stream: Some(stream),
+        cycles: 0,
+        quantum: 0,
+        peer_quantum: 0,
+        pending: None,
+    }
+}
+
+/* Waits for the other emulator to connect to `address` */
+pub fn listen(address: &str) -> Result<SocketLink, String> {
+    println!("Waiting for a link cable connection on {}", address);
+
+    let stream: Box<dyn Stream> = if address.starts_with("unix:") {
+        listen_unix(&address[5..])?
+    } else {
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+        let (stream, _) = listener
+            .accept()
+            .map_err(|e| format!("Could not accept a connection on {}: {}", address, e))?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        Box::new(stream)
+    };
+
+    Ok(from_stream(stream))
+}
+
+/* Connects to another emulator listening on `address` */
+pub fn connect(address: &str) -> Result<SocketLink, String> {
+    let stream: Box<dyn Stream> = if address.starts_with("unix:") {
+        connect_unix(&address[5..])?
+    } else {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("Could not connect to {}: {}", address, e))?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        Box::new(stream)
+    };
+
+    Ok(from_stream(stream))
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &str) -> Result<Box<dyn Stream>, String> {
+    /* a socket file left behind by an earlier run would stop us binding */
+    let _ = std::fs::remove_file(path);
+
+    let listener =
+        UnixListener::bind(path).map_err(|e| format!("Could not listen on {}: {}", path, e))?;
+    let (stream, _) = listener
+        .accept()
+        .map_err(|e| format!("Could not accept a connection on {}: {}", path, e))?;
+
+    Ok(Box::new(stream))
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &str) -> Result<Box<dyn Stream>, String> {
+    let stream =
+        UnixStream::connect(path).map_err(|e| format!("Could not connect to {}: {}", path, e))?;
+
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_: &str) -> Result<Box<dyn Stream>, String> {
+    Err(String::from("Unix domain sockets aren't supported on this platform"))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_: &str) -> Result<Box<dyn Stream>, String> {
+    Err(String::from("Unix domain sockets aren't supported on this platform"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_transfer_between_ends() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let mut master = from_stream(Box::new(a));
+        let mut slave = from_stream(Box::new(b));
+
+        let slave = thread::spawn(move || {
+            // runs well past the master, the lockstep holds it back until the transfer arrives
+            loop {
+                slave.tick(4);
+                if let Some(v) = slave.poll(0x99) {
+                    return (v, slave.quantum);
+                }
+            }
+        });
+
+        for _ in 0..QUANTUM {
+            master.tick(4);
+        }
+
+        assert_eq!(master.transfer(0x42), 0x99);
+
+        let (v, quantum) = slave.join().unwrap();
+        assert_eq!(v, 0x42);
+        assert!(quantum <= 6);
+    }
+}
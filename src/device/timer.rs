@@ -0,0 +1,233 @@
+use device::Device;
+use device::interrupt::{Flag, Interrupt};
+
+/* The timer registers, addressed relative to 0xFF04:
+ *
+ * 0: DIV  - the top 8 bits of a 16 bit counter that goes up every cycle, writing resets it
+ * 1: TIMA - the timer counter, requests the Timer interrupt when it overflows
+ * 2: TMA  - the value TIMA is reloaded with after an overflow
+ * 3: TAC  - Bit 2: Timer enable
+ *           Bits 1-0: Input clock select (00=4096Hz, 01=262144Hz, 10=65536Hz, 11=16384Hz)
+ *
+ * TIMA isn't clocked by a divided clock of its own, it's incremented on the falling edge of one
+ * bit of the internal divider (selected by TAC) ANDed with the enable bit. Because it's an edge
+ * detector anything that takes that signal from high to low increments TIMA, so resetting DIV
+ * while the selected bit is set, or changing TAC so the signal drops, both tick the timer.
+ *
+ * When TIMA overflows it reads 0 for one M-cycle before it's reloaded from TMA and the interrupt
+ * is requested. Writing TIMA during that cycle cancels the reload, writing it on the cycle the
+ * reload happens is ignored, and writing TMA on that cycle also lands in TIMA.
+ *
+ * The APU's frame sequencer hangs off the same divider, it's clocked at 512Hz by the falling edge
+ * of bit 12 (bit 4 of DIV), so resetting DIV while that bit is set clocks it too.
+ */
+pub struct Timer {
+    divider: u16,
+    counter: u8,
+    modulo: u8,
+    control: u8,
+    overflow: bool,
+    reloaded: bool,
+    frame_sequencer_clocks: u8,
+}
+
+impl Device for Timer {
+    fn get(&self, a: u16) -> u8 {
+        match a {
+            0 => (self.divider >> 8) as u8,
+            1 => self.counter,
+            2 => self.modulo,
+            3 => self.control | 0xF8,
+            _ => panic!("timer address out of range: {:X}", a),
+        }
+    }
+
+    fn set(&mut self, a: u16, v: u8) {
+        match a {
+            0 => {
+                let signal = self.signal();
+                if self.divider & 0x1000 != 0 {
+                    self.frame_sequencer_clocks += 1;
+                }
+                self.divider = 0;
+                if signal {
+                    self.increment();
+                }
+            }
+            1 => {
+                if !self.reloaded {
+                    self.counter = v;
+                    self.overflow = false;
+                }
+            }
+            2 => {
+                self.modulo = v;
+                if self.reloaded {
+                    self.counter = v;
+                }
+            }
+            3 => {
+                let signal = self.signal();
+                self.control = v & 0x07;
+                if signal && !self.signal() {
+                    self.increment();
+                }
+            }
+            _ => panic!("timer address out of range: {:X}", a),
+        }
+    }
+}
+
+impl Timer {
+    /* the divider bit TIMA watches, ANDed with the timer enable */
+    fn signal(&self) -> bool {
+        let bit = match self.control & 0x03 {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            _ => 7,
+        };
+
+        self.control & 0x04 != 0 && self.divider & (1 << bit) != 0
+    }
+
+    fn increment(&mut self) {
+        let (counter, overflow) = self.counter.overflowing_add(1);
+        self.counter = counter;
+        self.overflow = overflow;
+    }
+
+    /* Runs the timer for a single M-cycle (4 cycles). */
+    fn step(&mut self, interrupts: &mut Interrupt) {
+        self.reloaded = false;
+
+        if self.overflow {
+            self.overflow = false;
+            self.reloaded = true;
+            self.counter = self.modulo;
+            interrupts.request(Flag::Timer);
+        }
+
+        let signal = self.signal();
+        let frame_sequencer = self.divider & 0x1000 != 0;
+        self.divider = self.divider.wrapping_add(4);
+        if signal && !self.signal() {
+            self.increment();
+        }
+        if frame_sequencer && self.divider & 0x1000 == 0 {
+            self.frame_sequencer_clocks += 1;
+        }
+    }
+
+    /* The frame sequencer clocks since this was last called */
+    pub fn take_frame_sequencer_clocks(&mut self) -> u8 {
+        let clocks = self.frame_sequencer_clocks;
+        self.frame_sequencer_clocks = 0;
+        clocks
+    }
+
+    pub fn tick(&mut self, cycles: u32, interrupts: &mut Interrupt) {
+        for _ in 0..cycles / 4 {
+            self.step(interrupts);
+        }
+    }
+}
+
+pub fn new() -> Timer {
+    Timer {
+        divider: 0,
+        counter: 0,
+        modulo: 0,
+        control: 0,
+        overflow: false,
+        reloaded: false,
+        frame_sequencer_clocks: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divider() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut timer = new();
+
+        timer.tick(256 * 3, &mut interrupts);
+        assert_eq!(timer.get(0), 3);
+
+        timer.set(0, 0x55);
+        assert_eq!(timer.get(0), 0);
+    }
+
+    #[test]
+    fn test_overflow_reloads_after_a_cycle() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut timer = new();
+
+        // 262144Hz, TIMA goes up every 16 cycles
+        timer.set(3, 0x05);
+        timer.set(2, 0xF0);
+        timer.set(1, 0xFF);
+
+        timer.tick(16, &mut interrupts);
+        assert_eq!(timer.get(1), 0x00);
+        assert_eq!(interrupts.get(), 0x00);
+
+        timer.tick(4, &mut interrupts);
+        assert_eq!(timer.get(1), 0xF0);
+        assert_eq!(interrupts.get(), 0x04);
+    }
+
+    #[test]
+    fn test_writing_tima_cancels_reload() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut timer = new();
+
+        timer.set(3, 0x05);
+        timer.set(2, 0xF0);
+        timer.set(1, 0xFF);
+
+        timer.tick(16, &mut interrupts);
+        timer.set(1, 0x10);
+        timer.tick(4, &mut interrupts);
+
+        assert_eq!(timer.get(1), 0x10);
+        assert_eq!(interrupts.get(), 0x00);
+    }
+
+    #[test]
+    fn test_div_and_tac_glitches() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut timer = new();
+
+        // bit 3 of the divider is set after 8 cycles, resetting DIV drops it
+        timer.set(3, 0x05);
+        timer.tick(8, &mut interrupts);
+        assert_eq!(timer.get(1), 0);
+        timer.set(0, 0);
+        assert_eq!(timer.get(1), 1);
+
+        // disabling the timer while the bit is set drops it as well
+        timer.tick(8, &mut interrupts);
+        timer.set(3, 0x01);
+        assert_eq!(timer.get(1), 2);
+        assert_eq!(timer.get(3), 0xF9);
+    }
+
+    #[test]
+    fn test_frame_sequencer_clocks() {
+        let mut interrupts = Interrupt { storage: 0 };
+        let mut timer = new();
+
+        timer.tick(8192 * 2, &mut interrupts);
+        assert_eq!(timer.take_frame_sequencer_clocks(), 2);
+        assert_eq!(timer.take_frame_sequencer_clocks(), 0);
+
+        // resetting DIV with bit 4 set is a falling edge as well
+        timer.tick(4096, &mut interrupts);
+        timer.set(0, 0);
+        assert_eq!(timer.take_frame_sequencer_clocks(), 1);
+    }
+}
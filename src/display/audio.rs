@@ -0,0 +1,80 @@
+use sdl2;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use device::apu;
+use resampler;
+
+/* How much audio to keep queued ahead of the device, in seconds. Enough to ride out a slow frame
+ * without running dry, small enough that the sound doesn't lag the picture.
+ */
+const LATENCY: f32 = 0.05;
+
+/* Plays the APU's samples through an SDL audio queue.
+ *
+ * The samples are resampled to whatever rate the device was opened at and have their DC offset
+ * removed before they're queued. Since the device plays them back at exactly the rate it asks
+ * for, waiting for the queue to drain is also what keeps the emulator running at the gameboy's
+ * own speed.
+ */
+pub struct Audio {
+    queue: AudioQueue<f32>,
+    resampler: resampler::Resampler,
+    dc_blocker: resampler::DcBlocker,
+    buffer: Vec<f32>,
+    latency: u32,
+}
+
+/* None when there's no audio device to play to */
+pub fn new(sdl_context: &sdl2::Sdl) -> Option<Audio> {
+    let audio = sdl_context.audio().ok()?;
+
+    let desired = AudioSpecDesired {
+        freq: Some(48000),
+        channels: Some(2),
+        samples: Some(1024),
+    };
+
+    let queue: AudioQueue<f32> = match audio.open_queue(None, &desired) {
+        Ok(q) => q,
+        Err(e) => {
+            println!("Could not open audio device: {}", e);
+            return None;
+        }
+    };
+
+    let rate = queue.spec().freq as u32;
+    let channels = queue.spec().channels as u32;
+    queue.resume();
+
+    Some(Audio {
+        queue: queue,
+        resampler: resampler::new(2, apu::SAMPLE_RATE, rate),
+        dc_blocker: resampler::new_dc_blocker(2, rate),
+        buffer: Vec::new(),
+        latency: (rate as f32 * LATENCY) as u32 * channels * 4,
+    })
+}
+
+impl Audio {
+    pub fn play(&mut self, samples: &[apu::Sample]) {
+        let mut stereo = Vec::with_capacity(samples.len() * 2);
+        for s in samples {
+            stereo.push(s.left);
+            stereo.push(s.right);
+        }
+
+        self.buffer.clear();
+        self.resampler.process(&stereo, &mut self.buffer);
+        self.dc_blocker.process(&mut self.buffer);
+        self.queue.queue(&self.buffer);
+    }
+
+    /* Waits until the queue is down to LATENCY, if emulation is running behind this returns
+     * straight away and lets it catch up.
+     */
+    pub fn sync(&self, timer: &mut sdl2::TimerSubsystem) {
+        while self.queue.size() > self.latency {
+            timer.delay(1);
+        }
+    }
+}
@@ -76,3 +76,22 @@ pub fn draw(
     }
 }
 
+
+/* Flashes a border around the screen while a rumble cartridge has its motor running.
+ */
+pub fn draw_rumble(canvas: &mut Canvas<Window>, scale: u32) {
+    let width = 160 * scale;
+    let height = 144 * scale;
+    let border = scale * 2;
+
+    canvas.set_draw_color(Color::RGBA(255, 0, 0, 255));
+
+    let edges = [
+        Rect::new(0, 0, width, border),
+        Rect::new(0, (height - border) as i32, width, border),
+        Rect::new(0, 0, border, height),
+        Rect::new((width - border) as i32, 0, border, height),
+    ];
+
+    canvas.fill_rects(&edges).unwrap();
+}
@@ -7,13 +7,17 @@ use sdl2::video::Window;
 use sdl2::ttf;
 
 use config;
+use device::joypad::Button;
 use framebuffer;
 use gameboy;
+use gbs;
 use palette;
 // use repl;
 
+mod audio;
 mod rate_limiter;
 mod canvas;
+mod rumble;
 
 enum RunningState {
     Frame,
@@ -28,7 +32,25 @@ enum State {
     TileMap,
 }
 
-/* Display 
+/* The keyboard layout for the gameboy's buttons */
+fn get_button(keycode: Keycode) -> Option<Button> {
+    match keycode {
+        Keycode::Up => Some(Button::Up),
+        Keycode::Down => Some(Button::Down),
+        Keycode::Left => Some(Button::Left),
+        Keycode::Right => Some(Button::Right),
+        Keycode::X => Some(Button::A),
+        Keycode::Z => Some(Button::B),
+        Keycode::Return => Some(Button::Start),
+        Keycode::Backspace => Some(Button::Select),
+        _ => None,
+    }
+}
+
+/* Display
+
+   Emulation is paced by the audio queue while the gameboy runs continuously, falling back on
+   limiting the frame rate when there's no audio device or the gameboy is stepping or paused.
  */
 pub struct Display {
     frame_count: u32,
@@ -38,6 +60,8 @@ pub struct Display {
     scale: u32,
     sdl_context: sdl2::Sdl,
     timer: sdl2::TimerSubsystem,
+    rumble: rumble::Rumble,
+    audio: Option<audio::Audio>,
 }
 
 pub fn new<'a, 'b>(config: &config::Config) -> Display {
@@ -56,6 +80,8 @@ pub fn new<'a, 'b>(config: &config::Config) -> Display {
     let canvas = window.into_canvas().software().build().unwrap();
 
     let timer = sdl_context.timer().unwrap();
+    let rumble = rumble::new(&sdl_context);
+    let audio = audio::new(&sdl_context);
 
     Display {
 
@@ -66,11 +92,13 @@ pub fn new<'a, 'b>(config: &config::Config) -> Display {
         scale: scale,
         sdl_context: sdl_context,
         timer: timer,
+        rumble: rumble,
+        audio: audio,
     }
 }
 
 impl Display {
-    fn handle_event(&mut self, event: Event) {
+    fn handle_event(&mut self, event: Event, gameboy: &mut gameboy::Gameboy) {
         match event {
             Event::KeyDown {
                 keycode: Option::Some(Keycode::Space),
@@ -85,17 +113,34 @@ impl Display {
                 ..
             } => self.state = State::TileMap,
             Event::KeyDown {
-                keycode: Option::Some(Keycode::Right),
+                keycode: Option::Some(Keycode::F),
                 ..
             } => {
                 self.state = State::Running(RunningState::Frame)
             },
             Event::KeyDown {
-                keycode: Option::Some(Keycode::Down),
+                keycode: Option::Some(Keycode::I),
                 ..
             } => {
                 self.state = State::Running(RunningState::Instruction)
             },
+            Event::KeyDown {
+                keycode: Option::Some(keycode),
+                repeat: false,
+                ..
+            } => {
+                if let Some(button) = get_button(keycode) {
+                    gameboy.set_button(button, true);
+                }
+            },
+            Event::KeyUp {
+                keycode: Option::Some(keycode),
+                ..
+            } => {
+                if let Some(button) = get_button(keycode) {
+                    gameboy.set_button(button, false);
+                }
+            },
             _ => {}
         }
     }
@@ -123,6 +168,68 @@ impl Display {
         }
     }
 
+    /* Plays the songs in a GBS file, left and right change song */
+    pub fn play_gbs(&mut self, gbs: &gbs::Gbs, first_song: u8) {
+        let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+
+        let mut rate_limiter = rate_limiter::new(60);
+
+        let ttf_context = ttf::init().unwrap();
+        let debug_text = canvas::DebugText::new(&ttf_context);
+
+        let songs = gbs.header.songs;
+        let mut song = first_song;
+        let mut gameboy = match gbs.gameboy(&self.config, song) {
+            Ok(gameboy) => gameboy,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+
+        'mainloop: loop {
+            while !gameboy.next_instruction(&mut framebuffer) {}
+
+            let samples = gameboy.take_samples();
+            match self.audio {
+                Some(ref mut audio) => {
+                    audio.play(&samples);
+                    audio.sync(&mut self.timer);
+                }
+                None => rate_limiter.limit(&mut self.timer),
+            }
+
+            self.canvas.clear();
+            debug_text.draw(&mut self.canvas, &format!("{}/{}", song + 1, songs));
+            self.canvas.present();
+
+            let mut events = self.sdl_context.event_pump().unwrap();
+
+            for event in events.poll_iter() {
+                let next = match event {
+                    Event::Quit { .. } | Event::KeyDown { keycode: Option::Some(Keycode::Escape), ..  } => {
+                        break 'mainloop
+                    },
+                    Event::KeyDown { keycode: Option::Some(Keycode::Left), .. } => {
+                        if song == 0 { songs - 1 } else { song - 1 }
+                    },
+                    Event::KeyDown { keycode: Option::Some(Keycode::Right), .. } => {
+                        if song + 1 == songs { 0 } else { song + 1 }
+                    },
+                    _ => continue,
+                };
+
+                match gbs.gameboy(&self.config, next) {
+                    Ok(g) => {
+                        gameboy = g;
+                        song = next;
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
+        }
+    }
+
     fn toggle_paused(&mut self) {
         match self.state {
             State::Paused => self.state = State::Running(RunningState::Continuous),
@@ -188,13 +295,32 @@ impl Display {
                         }
                     }
 
+                    let samples = gameboy.take_samples();
+                    if let Some(ref mut audio) = self.audio {
+                        audio.play(&samples);
+                    }
+
                     canvas::draw(&mut self.canvas, &framebuffer, self.scale);
 
-                    if self.config.debug.frame_count {
+                    self.rumble.update(gameboy.rumble_active());
+                    if self.rumble.is_active() {
+                        canvas::draw_rumble(&mut self.canvas, self.scale);
+                    }
+
+                    if gameboy.locked() {
+                        debug_text.draw(&mut self.canvas, "CPU locked up")
+                    } else if self.config.debug.frame_count {
                         debug_text.draw(&mut self.canvas, &format!("F:{}", self.frame_count))
                     }
                     self.canvas.present();
                     self.break_at_frame(gameboy);
+
+                    /* flush save ram every few seconds so a crash doesn't lose much progress */
+                    if self.frame_count % 300 == 0 {
+                        if let Err(e) = gameboy.save_if_dirty() {
+                            println!("Could not write save file: {}", e);
+                        }
+                    }
                 }
                 State::TileData => {
                     gameboy.render_tile_data(&mut framebuffer);
@@ -211,6 +337,7 @@ impl Display {
                     self.canvas.present();
                 }
                 State::Paused => {
+                    self.rumble.update(false);
                     self.canvas.clear();
                     canvas::draw(&mut self.canvas, &framebuffer, self.scale);
                     debug_text.draw(&mut self.canvas, "Paused");
@@ -218,7 +345,16 @@ impl Display {
                 }
             }
 
-            rate_limiter.limit(&mut self.timer);
+            match (&self.state, &self.audio) {
+                (State::Running(RunningState::Continuous), Some(audio)) => {
+                    audio.sync(&mut self.timer)
+                }
+                _ => {
+                    /* samples from stepping through aren't worth playing */
+                    gameboy.take_samples();
+                    rate_limiter.limit(&mut self.timer)
+                }
+            }
 
             let mut events = self.sdl_context.event_pump().unwrap();
 
@@ -227,7 +363,7 @@ impl Display {
                     Event::Quit { .. } | Event::KeyDown { keycode: Option::Some(Keycode::Escape), ..  } => {
                         break 'mainloop
                     },
-                    _ => self.handle_event(event)
+                    _ => self.handle_event(event, gameboy)
                 }
             }
         }
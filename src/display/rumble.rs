@@ -0,0 +1,55 @@
+use sdl2;
+use sdl2::haptic::Haptic;
+
+/* Mirrors a rumble cartridge's motor onto the first haptic capable joystick, if there is one.
+ * The display flashes an overlay regardless so rumble is visible without a controller.
+ */
+pub struct Rumble {
+    _joystick: Option<sdl2::JoystickSubsystem>,
+    haptic: Option<Haptic>,
+    active: bool,
+}
+
+fn open_haptic(sdl_context: &sdl2::Sdl) -> (Option<sdl2::JoystickSubsystem>, Option<Haptic>) {
+    let joystick = match sdl_context.joystick() {
+        Ok(j) => j,
+        Err(_) => return (None, None),
+    };
+
+    let haptic = match (joystick.num_joysticks(), sdl_context.haptic()) {
+        (Ok(n), Ok(h)) if n > 0 => h.open_from_joystick_id(0).ok(),
+        _ => None,
+    };
+
+    (Some(joystick), haptic)
+}
+
+pub fn new(sdl_context: &sdl2::Sdl) -> Rumble {
+    let (joystick, haptic) = open_haptic(sdl_context);
+
+    Rumble {
+        _joystick: joystick,
+        haptic: haptic,
+        active: false,
+    }
+}
+
+impl Rumble {
+    /* Called once a frame with the motor state, the effect is replayed every frame for a little
+     * longer than a frame so it stops on its own if the emulator pauses.
+     */
+    pub fn update(&mut self, active: bool) {
+        if let Some(ref mut haptic) = self.haptic {
+            if active {
+                haptic.rumble_play(0.75, 50);
+            } else if self.active {
+                haptic.rumble_stop();
+            }
+        }
+        self.active = active;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
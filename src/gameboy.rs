@@ -1,3 +1,8 @@
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+use bus;
 use cpu;
 use config;
 use device;
@@ -8,10 +13,14 @@ use instructions;
 use mmu;
 use palette;
 use registers;
+use save_file;
 
+use device::apu;
 use device::boot_rom;
 use device::cartridge;
-use device::interrupt;
+use device::cartridge_header;
+use device::joypad;
+use device::serial;
 
 /* Represents the gameboy device. Owns all the components needed to get it working.
  * However the actual loop is controlled by the display since SDL2 wants to own the
@@ -24,9 +33,12 @@ pub struct Gameboy {
     registers: registers::Registers,
     instructions: instructions::Instructions,
     cycle_count: u32,
+    /* every cycle run since power on, linked gameboys are kept in step by it */
+    total_cycles: u64,
     mmu: mmu::MMU,
     cpu: cpu::CPU,
     gpu: gpu::GPU,
+    save_file: Option<PathBuf>,
 }
 
 impl Gameboy {
@@ -38,42 +50,83 @@ impl Gameboy {
         self.cpu.set_log_instructions(state);
     }
 
-    /* Executes an instruction (which returns the number of cycles it took) when the cycle count
-     * exceeds 70244 it returns true to signal that a new frame is available. Gameboy frame timings
-     * are based on cycles and 70244 is the number of frames a gameboy takes to render a full
-     * frame.
+    /* Presses or releases one of the gameboy's buttons, frontends call this as their input
+     * changes.
+     */
+    pub fn set_button(&mut self, button: joypad::Button, pressed: bool) {
+        let hardware_io = &mut self.mmu.hardware_io;
+        hardware_io.joypad.set_button(button, pressed, &mut hardware_io.interrupts);
+    }
+
+    /* Plugs `link` into the serial port, the port starts out with nothing connected. */
+    pub fn set_link(&mut self, link: Box<dyn serial::Link>) {
+        self.mmu.hardware_io.serial.set_link(link);
+    }
+
+    /* True once the game has executed an illegal opcode and hung the cpu */
+    pub fn locked(&self) -> bool {
+        self.cpu.locked()
+    }
+
+    /* True while a rumble cartridge has its motor switched on, frontends poll this once a frame
+     * to drive whatever feedback they have available.
+     */
+    pub fn rumble_active(&self) -> bool {
+        self.mmu.cartridge.rumble()
+    }
+
+    #[cfg(test)]
+    pub fn peek(&self, address: u16) -> u8 {
+        self.mmu.get(address)
+    }
+
+    /* The APU's output since this was last called, at apu::SAMPLE_RATE */
+    pub fn take_samples(&mut self) -> Vec<apu::Sample> {
+        self.mmu.hardware_io.apu.take_samples()
+    }
+
+    /* Executes an instruction or dispatches an interrupt, the rest of the hardware is run forward
+     * M-cycle by M-cycle through the bus as the cpu makes its memory accesses.
      *
      * This function takes as its input a `framebuffer` which is an array of palette::Shades how to
      * render a shade is up to the display.
      *
-     * Returns true if a frame is ready.
+     * Returns true if a frame is ready. That's when the PPU enters VBlank, while the LCD is
+     * switched off (or the gameboy is stopped) there's nothing driving frames so we fall back to
+     * signalling one every 70224 cycles (the length of a frame) to keep the frontend going.
      */
     pub fn next_instruction(&mut self, framebuffer: &mut framebuffer::Framebuffer) -> bool {
-        let cycles = self.cpu.tick(&self.instructions, &mut self.registers, &mut self.mmu);
+        let cycles = {
+            let mut bus = bus::new(&mut self.mmu, &mut self.gpu, framebuffer);
+            self.cpu.tick(&self.instructions, &mut self.registers, &mut bus)
+        };
+
+        self.total_cycles += cycles as u64;
+
+        /* in STOP mode the timer and ppu are frozen along with the cpu's clock */
+        if self.cpu.stopped() {
+            return self.count_frame(cycles);
+        }
 
         if self.mmu.hardware_io
             .lcd_control_register
             .get_flag(device::hardware_io::LCDControlFlag::LCDDisplayEnable)
         {
-            self.gpu.tick(&mut self.mmu, cycles, framebuffer);
+            self.cycle_count = 0;
+            return self.gpu.take_frame();
         }
 
-        if self.registers.get_interrupts_enabled() {
-            let enabled = self.mmu.interrupt_enable.get_enabled_interrupts();
-            let interrupts = self.mmu.hardware_io.interrupts.get_interrupts(enabled);
-
-            for i in interrupts {
-                self.registers.set_interrupts_enabled(false);
-                interrupt::handle_interrupt(&mut self.registers, &mut self.mmu, i);
-            }
-        }
+        self.count_frame(cycles)
+    }
 
+    /* Signals a frame every 70224 cycles, for when the PPU isn't there to */
+    fn count_frame(&mut self, cycles: u8) -> bool {
         self.cycle_count += cycles as u32;
 
-        if self.cycle_count >= 70244 {
-            /* if we crossed 70244 we want to loop back around
+        if self.cycle_count >= 70224 {
+            /* if we crossed 70224 we want to loop back around
              */
-            self.cycle_count -= 70244;
+            self.cycle_count -= 70224;
             true
         } else {
             false
@@ -118,22 +171,114 @@ impl Gameboy {
         }
     }
 
+    /* Writes battery backed cartridge ram to the save file, cartridges without a battery have
+     * nothing worth saving.
+     */
+    pub fn save(&mut self) -> io::Result<()> {
+        match self.save_file {
+            Some(ref path) if self.mmu.cartridge.has_battery() => {
+                self.mmu.cartridge.take_ram_dirty();
+                save_file::write(path, &self.mmu.cartridge)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /* Frontends call this periodically so a crash only loses what was written since */
+    pub fn save_if_dirty(&mut self) -> io::Result<()> {
+        if self.mmu.cartridge.has_battery() && self.mmu.cartridge.take_ram_dirty() {
+            self.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn cartridge_header(&self) -> &cartridge_header::CartridgeHeader {
+        self.mmu.cartridge.header()
+    }
+
     pub fn get_tile_maps(&self) -> (device::tile_map::TileMap, device::tile_map::TileMap) {
         (self.mmu.tile_map_1.clone(), self.mmu.tile_map_2.clone())
     }
 }
 
-pub fn new(config: &config::Config) -> Gameboy {
-    let mut boot_rom = config.read_boot_rom().unwrap();
-    let mut game_rom = config.read_game_rom().unwrap();
+/* Loads the boot rom and game rom named in the config along with the game's save file. */
+pub fn new(config: &config::Config) -> Result<Gameboy, String> {
+    let mut boot_rom = Vec::new();
+    config
+        .read_boot_rom()
+        .and_then(|mut f| f.read_to_end(&mut boot_rom))
+        .map_err(|e| format!("Could not read boot rom {}: {}", config.boot_rom, e))?;
+
+    let mut rom = Vec::new();
+    config
+        .read_game_rom()
+        .and_then(|mut f| f.read_to_end(&mut rom))
+        .map_err(|e| format!("Could not read game rom {}: {}", config.game_rom, e))?;
+
+    let mut gameboy = from_roms(config, &boot_rom, rom)?;
+
+    let save_path = PathBuf::from(&config.save_file);
+    if gameboy.mmu.cartridge.has_battery() {
+        save_file::load(&save_path, &mut gameboy.mmu.cartridge, config.rtc_wall_clock)
+            .map_err(|e| format!("Could not read save file {}: {}", config.save_file, e))?;
+    }
+    gameboy.save_file = Some(save_path);
+
+    Ok(gameboy)
+}
+
+/* Builds a gameboy from roms already in memory, it has no save file. The game rom's header
+ * decides which memory bank controller and how much cartridge ram to build, roms we can't run
+ * are an error.
+ */
+pub fn from_roms(config: &config::Config, boot_rom: &[u8], rom: Vec<u8>) -> Result<Gameboy, String> {
+    let header = cartridge_header::parse(&rom)?;
+
+    if !header.logo_valid() {
+        println!("Warning: cartridge logo doesn't match, real hardware would refuse to boot it");
+    }
+    if !header.header_checksum_valid {
+        println!("Warning: cartridge header checksum doesn't match, real hardware would refuse to boot it");
+    }
+
+    let cartridge = cartridge::new(header, rom)?;
 
-    Gameboy {
+    Ok(Gameboy {
         registers: registers::new(),
         instructions: instructions::new(),
         cycle_count: 0,
-        mmu: mmu::new(boot_rom::new(&mut boot_rom), cartridge::new(&mut game_rom)),
+        total_cycles: 0,
+        mmu: mmu::new(boot_rom::new(boot_rom), cartridge),
         cpu: cpu::new(config.clone()),
         gpu: gpu::new(),
+        save_file: None,
+    })
+}
+
+/* Connects the serial ports of two gameboys with a link cable */
+#[cfg(test)]
+pub fn link(a: &mut Gameboy, b: &mut Gameboy) {
+    let (end_a, end_b) = serial::pair();
+    a.set_link(Box::new(end_a));
+    b.set_link(Box::new(end_b));
+}
+
+/* Runs a pair of linked gameboys forward by a single instruction on whichever of them is behind,
+ * so neither gets more than an instruction ahead of the other and the two see each other's
+ * transfers at the same point every run. Returns which of them have a frame ready.
+ */
+#[cfg(test)]
+pub fn next_instruction_linked(
+    a: &mut Gameboy,
+    b: &mut Gameboy,
+    framebuffer_a: &mut framebuffer::Framebuffer,
+    framebuffer_b: &mut framebuffer::Framebuffer,
+) -> (bool, bool) {
+    if a.total_cycles <= b.total_cycles {
+        (a.next_instruction(framebuffer_a), false)
+    } else {
+        (false, b.next_instruction(framebuffer_b))
     }
 }
 
@@ -193,6 +338,57 @@ mod tests {
         ).collect::<Vec<State>>()
     }
 
+    /* A 32KiB rom only cartridge running `program` from 0x100 */
+    fn test_rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    #[test]
+    fn linked_gameboys_exchange_a_byte() {
+        let config = config::zero();
+
+        // waits a little so the other side is ready, then sends 0x42 on the internal clock
+        let master = test_rom(&[
+            0x06, 0x10,         // LD B,0x10
+            0x05,               // DEC B
+            0x20, 0xFD,         // JR NZ,-3
+            0x3E, 0x42,         // LD A,0x42
+            0xE0, 0x01,         // LDH (0x01),A
+            0x3E, 0x81,         // LD A,0x81
+            0xE0, 0x02,         // LDH (0x02),A
+            0x18, 0xFE,         // JR -2
+        ]);
+
+        // loads 0x99 and waits on the external clock
+        let slave = test_rom(&[
+            0x3E, 0x99,         // LD A,0x99
+            0xE0, 0x01,         // LDH (0x01),A
+            0x3E, 0x80,         // LD A,0x80
+            0xE0, 0x02,         // LDH (0x02),A
+            0x18, 0xFE,         // JR -2
+        ]);
+
+        let mut a = super::from_roms(&config, &[], master).unwrap();
+        let mut b = super::from_roms(&config, &[], slave).unwrap();
+        super::link(&mut a, &mut b);
+
+        let mut framebuffer_a: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+        let mut framebuffer_b: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+
+        while a.total_cycles < 20000 || b.total_cycles < 20000 {
+            super::next_instruction_linked(&mut a, &mut b, &mut framebuffer_a, &mut framebuffer_b);
+        }
+
+        assert_eq!(a.mmu.get(0xFF01), 0x99);
+        assert_eq!(a.mmu.get(0xFF02), 0x7F);
+        assert_eq!(b.mmu.get(0xFF01), 0x42);
+        assert_eq!(b.mmu.get(0xFF02), 0x7E);
+        assert_eq!(a.mmu.hardware_io.interrupts.get() & 0x08, 0x08);
+        assert_eq!(b.mmu.hardware_io.interrupts.get() & 0x08, 0x08);
+    }
+
     #[test]
     fn boot_rom_states_are_exact() {
         let states = read_state_file(Path::new("./tests/state_files/boot_rom_states"));
@@ -200,10 +396,12 @@ mod tests {
         let config = config::Config {
             boot_rom: String::from("../gb_test_roms/DMG_ROM.bin"),
             game_rom: String::from("./tests/cpu_instrs_01_special.gb"),
+            save_file: String::from("./tests/cpu_instrs_01_special.sav"),
+            rtc_wall_clock: false,
             debug: config::debug_default(),
         };
 
-        let mut gameboy = super::new(&config);
+        let mut gameboy = super::new(&config).unwrap();
         let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
 
         gameboy.next_instruction(&mut framebuffer);
@@ -222,10 +420,12 @@ mod tests {
         let config = config::Config {
             boot_rom: String::from("../gb_test_roms/DMG_ROM.bin"),
             game_rom: String::from("./tests/cpu_instrs_06_ld_r_r.gb"),
+            save_file: String::from("./tests/cpu_instrs_06_ld_r_r.sav"),
+            rtc_wall_clock: false,
             debug: config::debug_default(),
         };
 
-        let mut gameboy = super::new(&config);
+        let mut gameboy = super::new(&config).unwrap();
         let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
 
         while gameboy.get_pc() < 0x0100 {
@@ -0,0 +1,304 @@
+/* GBS files are music ripped from gameboy games, the sound engine and song data along with a
+ * header saying how to drive them:
+ *
+ * 0x00: "GBS"
+ * 0x03: Version (1)
+ * 0x04: Number of songs
+ * 0x05: First song (1 based)
+ * 0x06: Load address, where the data after the header goes in the rom
+ * 0x08: Init address, called with the song number (0 based) in A
+ * 0x0A: Play address, called at the rate below
+ * 0x0C: Stack pointer
+ * 0x0E: Timer modulo (TMA)
+ * 0x0F: Timer control (TAC), if the timer is enabled play is called on the Timer interrupt,
+ *       otherwise on VBlank
+ * 0x10: Title (32 bytes)
+ * 0x30: Author (32 bytes)
+ * 0x50: Copyright (32 bytes)
+ * 0x70: Data
+ *
+ * They're played by building a cartridge around them: the data goes in a MBC5 rom at its load
+ * address, which takes the full bank number GBS files write to 0x2000, and a small driver in the
+ * space before the load address sets up the hardware, calls init and then waits for interrupts
+ * that call play. RST n jumps to load address + n in a GBS file, so the vectors jump there.
+ */
+use std::fmt;
+use std::fs;
+
+use config;
+use device::cartridge_header;
+use gameboy;
+
+const HEADER_SIZE: usize = 0x70;
+
+/* Where the driver goes, just after the cartridge header */
+const DRIVER: usize = 0x0150;
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub songs: u8,
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl Header {
+    /* Play is called on the Timer interrupt rather than VBlank */
+    pub fn timer_driven(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:        {}", self.title)?;
+        writeln!(f, "Author:       {}", self.author)?;
+        writeln!(f, "Copyright:    {}", self.copyright)?;
+        writeln!(f, "Songs:        {}", self.songs)?;
+        if self.timer_driven() {
+            writeln!(f, "Rate:         Timer (TMA {:02X}, TAC {:02X})", self.timer_modulo, self.timer_control)
+        } else {
+            writeln!(f, "Rate:         VBlank")
+        }
+    }
+}
+
+pub struct Gbs {
+    pub header: Header,
+    data: Vec<u8>,
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Gbs, String> {
+    if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"GBS" {
+        return Err(String::from("Not a GBS file"));
+    }
+
+    if bytes[0x03] != 1 {
+        return Err(format!("Unsupported GBS version: {}", bytes[0x03]));
+    }
+
+    let header = Header {
+        songs: bytes[0x04],
+        first_song: bytes[0x05],
+        load_address: read_u16(&bytes[0x06..]),
+        init_address: read_u16(&bytes[0x08..]),
+        play_address: read_u16(&bytes[0x0A..]),
+        stack_pointer: read_u16(&bytes[0x0C..]),
+        timer_modulo: bytes[0x0E],
+        timer_control: bytes[0x0F],
+        title: read_string(&bytes[0x10..0x30]),
+        author: read_string(&bytes[0x30..0x50]),
+        copyright: read_string(&bytes[0x50..0x70]),
+    };
+
+    /* the driver and cartridge header live below the load address */
+    if header.load_address < 0x0400 || header.load_address >= 0x8000 {
+        return Err(format!("Unsupported GBS load address: {:04X}", header.load_address));
+    }
+
+    if header.songs == 0 {
+        return Err(String::from("GBS file has no songs"));
+    }
+
+    Ok(Gbs {
+        header: header,
+        data: bytes[HEADER_SIZE..].to_vec(),
+    })
+}
+
+pub fn load(path: &str) -> Result<Gbs, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Could not read GBS file {}: {}", path, e))?;
+    parse(&bytes)
+}
+
+fn lo(v: u16) -> u8 {
+    v as u8
+}
+
+fn hi(v: u16) -> u8 {
+    (v >> 8) as u8
+}
+
+impl Gbs {
+    /* Builds a cartridge rom that plays `song` (0 based) */
+    pub fn rom(&self, song: u8) -> Vec<u8> {
+        let h = &self.header;
+        let end = h.load_address as usize + self.data.len();
+
+        let mut size_code = 0;
+        while (0x8000 << size_code) < end && size_code < 8 {
+            size_code += 1;
+        }
+
+        let mut rom = vec![0xFF; 0x8000 << size_code];
+        let end = end.min(rom.len());
+        rom[h.load_address as usize..end].copy_from_slice(&self.data[..end - h.load_address as usize]);
+
+        for n in (0x00..0x40).step_by(8) {
+            let target = h.load_address + n as u16;
+            rom[n..n + 3].copy_from_slice(&[0xC3, lo(target), hi(target)]);
+        }
+
+        /* the interrupts call play and go back to waiting */
+        let call_play = [0xCD, lo(h.play_address), hi(h.play_address), 0xD9];
+        rom[0x0040..0x0044].copy_from_slice(&call_play);
+        rom[0x0050..0x0054].copy_from_slice(&call_play);
+
+        /* with no boot rom the cpu runs into the entry point, which jumps to the driver */
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, lo(DRIVER as u16), hi(DRIVER as u16)]);
+
+        for b in rom[0x0104..0x0150].iter_mut() {
+            *b = 0;
+        }
+        rom[0x0104..0x0134].copy_from_slice(&cartridge_header::NINTENDO_LOGO);
+        let title: Vec<u8> = h.title.bytes().filter(|b| b.is_ascii_graphic() || *b == b' ').take(15).collect();
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(&title);
+        /* MBC5 with 8KiB of ram */
+        rom[0x0147] = 0x1A;
+        rom[0x0148] = size_code;
+        rom[0x0149] = 0x02;
+        rom[0x014D] = cartridge_header::compute_header_checksum(&rom);
+
+        let (interrupt, lcd) = if h.timer_driven() { (0x04, 0x00) } else { (0x01, 0x80) };
+
+        let driver = [
+            0x3E, 0x01, 0xE0, 0x50,                    // LD A,0x01; LDH (0x50),A - unmap the boot rom
+            0x3E, 0x0A, 0xEA, 0x00, 0x00,              // LD A,0x0A; LD (0x0000),A - enable cartridge ram
+            0x31, lo(h.stack_pointer), hi(h.stack_pointer), // LD SP,nn
+            0x3E, 0x80, 0xE0, 0x26,                    // NR52: sound on
+            0x3E, 0x77, 0xE0, 0x24,                    // NR50: full volume
+            0x3E, 0xFF, 0xE0, 0x25,                    // NR51: every channel to both sides
+            0x3E, h.timer_modulo, 0xE0, 0x06,          // TMA
+            0x3E, h.timer_control & 0x07, 0xE0, 0x07,  // TAC
+            0x3E, song,                                // LD A,song
+            0xCD, lo(h.init_address), hi(h.init_address), // CALL init
+            0x3E, lcd, 0xE0, 0x40,                     // LCDC: on for VBlank driven files
+            0xAF, 0xE0, 0x0F,                          // XOR A; LDH (0x0F),A - clear IF
+            0x3E, interrupt, 0xE0, 0xFF,               // IE
+            0xFB,                                      // EI
+            0x76,                                      // HALT
+            0x18, 0xFD,                                // JR -3
+        ];
+        rom[DRIVER..DRIVER + driver.len()].copy_from_slice(&driver);
+
+        rom
+    }
+
+    /* A gameboy playing `song` (0 based) */
+    pub fn gameboy(&self, config: &config::Config, song: u8) -> Result<gameboy::Gameboy, String> {
+        if song >= self.header.songs {
+            return Err(format!("There's no song {}, the file has {}", song + 1, self.header.songs));
+        }
+
+        gameboy::from_roms(config, &[], self.rom(song))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* A GBS file whose init stores the song number at 0xC000 and whose play counts calls at
+     * 0xC001, driven by VBlank or by the timer
+     */
+    fn test_gbs(timer_control: u8) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(b"GBS\x01");
+        bytes[0x04] = 3;
+        bytes[0x05] = 1;
+        bytes[0x06..0x08].copy_from_slice(&[0x00, 0x04]);
+        bytes[0x08..0x0A].copy_from_slice(&[0x00, 0x04]);
+        bytes[0x0A..0x0C].copy_from_slice(&[0x04, 0x04]);
+        bytes[0x0C..0x0E].copy_from_slice(&[0xFE, 0xFF]);
+        bytes[0x0E] = 0x00;
+        bytes[0x0F] = timer_control;
+        bytes[0x10..0x14].copy_from_slice(b"Test");
+
+        bytes.extend_from_slice(&[
+            0xEA, 0x00, 0xC0,   // init: LD (0xC000),A
+            0xC9,               // RET
+            0xFA, 0x01, 0xC0,   // play: LD A,(0xC001)
+            0x3C,               // INC A
+            0xEA, 0x01, 0xC0,   // LD (0xC001),A
+            0xC9,               // RET
+        ]);
+        bytes
+    }
+
+    fn run(gbs: &Gbs, song: u8, frames: u32) -> gameboy::Gameboy {
+        let mut gameboy = gbs.gameboy(&config::zero(), song).unwrap();
+        ::capture::record(&mut gameboy, frames, 48000, false);
+        gameboy
+    }
+
+    #[test]
+    fn test_parse() {
+        let gbs = parse(&test_gbs(0x00)).unwrap();
+
+        assert_eq!(gbs.header.songs, 3);
+        assert_eq!(gbs.header.load_address, 0x0400);
+        assert_eq!(gbs.header.play_address, 0x0404);
+        assert_eq!(gbs.header.title, "Test");
+        assert!(!gbs.header.timer_driven());
+
+        assert!(parse(b"NES\x01").is_err());
+        assert!(gbs.gameboy(&config::zero(), 3).is_err());
+    }
+
+    #[test]
+    fn test_vblank_driven() {
+        let gbs = parse(&test_gbs(0x00)).unwrap();
+        let gameboy = run(&gbs, 2, 10);
+
+        assert_eq!(gameboy.peek(0xC000), 2);
+        // play runs once a frame, the first frame is spent turning the LCD on
+        let plays = gameboy.peek(0xC001);
+        assert!(plays >= 9 && plays <= 10, "{}", plays);
+    }
+
+    #[test]
+    fn test_rst_is_relocated() {
+        let mut bytes = test_gbs(0x00);
+        bytes.truncate(HEADER_SIZE);
+        bytes[0x0A..0x0C].copy_from_slice(&[0x0B, 0x04]);
+        bytes.extend_from_slice(&[
+            0xCF,               // init: RST 0x08
+            0xC9,               // RET
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xEA, 0x00, 0xC0,   // 0x0408: LD (0xC000),A
+            0xC9,               // play: RET
+        ]);
+
+        let gbs = parse(&bytes).unwrap();
+        let gameboy = run(&gbs, 2, 2);
+
+        assert_eq!(gameboy.peek(0xC000), 2);
+    }
+
+    #[test]
+    fn test_timer_driven() {
+        // 4096Hz with TMA 0, 16 calls a second
+        let gbs = parse(&test_gbs(0x04)).unwrap();
+        let gameboy = run(&gbs, 0, 60);
+
+        assert_eq!(gameboy.peek(0xC000), 0);
+        let plays = gameboy.peek(0xC001);
+        assert!(plays >= 15 && plays <= 16, "{}", plays);
+    }
+}
@@ -1,6 +1,9 @@
-use device::hardware_io::LCDControlFlag;
+use device::hardware_io::{LCDControlFlag, LCDModes, LCDStatusFlag};
+use device::interrupt::Flag;
+use device::object_attribute_memory::Sprite;
 use framebuffer;
 use mmu;
+use tile;
 
 #[derive(PartialEq)]
 pub enum Mode {
@@ -12,6 +15,9 @@ pub enum Mode {
 
 pub struct GPU {
     mode_clock: u32,
+    enabled: bool,
+    window_line: u8,
+    stat_line: bool,
     frame_available: bool,
     pub mode: Mode,
 }
@@ -19,52 +25,53 @@ pub struct GPU {
 pub fn new() -> GPU {
     GPU {
         mode_clock: 0,
+        enabled: false,
+        window_line: 0,
+        stat_line: false,
         mode: Mode::OAM,
         frame_available: false,
     }
 }
 
-fn render_line(mmu: &mmu::MMU, framebuffer: &mut framebuffer::Framebuffer) {
+/* There are two ways of addressing tile data. With tile data select set tiles are numbered 0-255
+ * from 0x8000. With it clear they're numbered -128-127 from 0x9000, so tiles 128-255 are shared
+ * between the two modes (0x8800-0x8FFF) while 0-127 come from 0x9000-0x97FF.
+ */
+fn get_background_tile(mmu: &mmu::MMU, index: u8) -> tile::Tile {
+    let tile_data_select = mmu.hardware_io
+        .lcd_control_register
+        .get_flag(LCDControlFlag::TileDataSelect);
+
+    if tile_data_select || index >= 128 {
+        mmu.tile_data_1.get_tile(index)
+    } else {
+        /* tile_data_2 starts at 0x8800 so tile 0 at 0x9000 is 128 tiles in */
+        mmu.tile_data_2.get_tile(index + 128)
+    }
+}
+
+/* Fills `line` with the background's colour indices (before the palette is applied), sprite
+ * priority needs to know which background pixels are colour 0.
+ */
+fn render_background(mmu: &mmu::MMU, line: &mut [u8; 160]) {
     // get our y-offset, this wont change per scan line
     let y_offset = mmu.hardware_io.lcd_line_count.get().wrapping_add(mmu.hardware_io.lcd_scroll_position_y);
 
-    /* Fetch the currently active palette
-     */
-    // let palette = mmu.hardware_io.background_palette.get_shades();
-
-    // let mut line:[palette::Shade;160] = [palette::Shade::White;160];
+    let tile_map_select = mmu.hardware_io
+        .lcd_control_register
+        .get_flag(LCDControlFlag::TileMapSelect);
 
-    let mut i = 0;
-    // for each pixel in a line
-    while i < 160 {
+    for i in 0..160u8 {
         /* The Tile Map is a 32x32 array where every byte is a reference to where in the tile data
-         * to pull tile data from.
-         *
-         * So for every line we're on, we jump forward 32 tiles. We take the current x scroll
-         * position and add to it where we are in rendering this line (i). Then we want to find
-         * which tile we would land on, so divide our current index (i) by 32 to find which of the
-         * 32 tiles we are on.
-         *
-         * We'll later need to know which index in the tile we're at so record that while we're at
-         * it.
-         */
-
-        /* x offset tells us which pixel in the line we're on. we have to take this and map it into
-         * which tile it would be
+         * to pull tile data from. Scroll x,y is where the screen sits in the 256x256 background
+         * and it wraps around at the edges.
          */
-        let x_offset = mmu.hardware_io.lcd_scroll_position_x + i;
+        let x_offset = mmu.hardware_io.lcd_scroll_position_x.wrapping_add(i);
 
-        // /* This block determines which tile we are on in the 32x32 grid.
+        // This block determines which tile we are on in the 32x32 grid.
         let tile_index_y = y_offset / 8;
         let tile_index_x = x_offset / 8;
 
-        let tile_map_select = mmu.hardware_io
-            .lcd_control_register
-            .get_flag(LCDControlFlag::TileMapSelect);
-        let tile_data_select = mmu.hardware_io
-            .lcd_control_register
-            .get_flag(LCDControlFlag::TileDataSelect);
-
         /* Figure out where to to find the data in the tile map index */
         let tile_map_index: u16 = (tile_index_y as u16 * 32) + tile_index_x as u16;
 
@@ -74,40 +81,210 @@ fn render_line(mmu: &mmu::MMU, framebuffer: &mut framebuffer::Framebuffer) {
             mmu.tile_map_1.get(tile_map_index)
         };
 
-        /* We check what tile data set is enabled and use the tile data index found previously to
-         * fetch a tile.
-         */
-        let tile = if tile_data_select {
-            mmu.tile_data_1.get_tile(tile_data_index)
+        let tile = get_background_tile(mmu, tile_data_index);
+
+        /* Once we have a tile we pick out the pixel we landed on */
+        let row = tile.get_row(y_offset % 8);
+        line[i as usize] = row[(x_offset % 8) as usize];
+    }
+}
+
+/* Draws the window over `line`, returns whether any of it was drawn. The window is a second
+ * background layer that doesn't scroll, its top left corner sits at (WX - 7, WY) on screen and
+ * it covers everything below and to the right of that.
+ *
+ * The window keeps its own line counter rather than using LY - WY. It only moves forward on lines
+ * where the window was actually drawn, so hiding the window part way down the screen and then
+ * showing it again carries on from the window line it stopped at.
+ */
+fn render_window(mmu: &mmu::MMU, window_line: u8, line: &mut [u8; 160]) -> bool {
+    let ly = mmu.hardware_io.lcd_line_count.get();
+    let wy = mmu.hardware_io.window_position_y;
+    let wx = mmu.hardware_io.window_position_x as i16 - 7;
+
+    if ly < wy || wx >= 160 {
+        return false;
+    }
+
+    let tile_map_select = mmu.hardware_io
+        .lcd_control_register
+        .get_flag(LCDControlFlag::WindowTileSelect);
+
+    let tile_index_y = window_line / 8;
+
+    for x in wx.max(0)..160 {
+        let window_x = (x - wx) as u8;
+        let tile_map_index: u16 = (tile_index_y as u16 * 32) + (window_x / 8) as u16;
+
+        let tile_data_index = if tile_map_select {
+            mmu.tile_map_2.get(tile_map_index)
         } else {
-            mmu.tile_data_2.get_tile(tile_data_index)
+            mmu.tile_map_1.get(tile_map_index)
         };
 
-        /* Once we have the tile we need to know which pixel we're on in the pixel
-         * in the x direction if we are on i = 0 then we need to take the x_offset and
-         * get the remainder from 8.
-         */
-        let pixel_index_y = y_offset % 8;
-        let pixel_index_x = if i == 0 { x_offset % 8 } else { 0 };
+        let tile = get_background_tile(mmu, tile_data_index);
+        let row = tile.get_row(window_line % 8);
+        line[x as usize] = row[(window_x % 8) as usize];
+    }
 
-        /* Once we have a tile, we need to actually index into the tile at the right location
-         * For each pixel in the tile render the pixel. Now... of course this can't be simple.
-         */
+    true
+}
+
+/* Finds the sprites drawn on `line`. The hardware scans OAM in order and stops at the first 10
+ * sprites that overlap the line, even if some of them are off screen horizontally. When sprites
+ * overlap the one with the smaller X wins, and for equal X the one earlier in OAM wins, so they
+ * are returned in drawing priority order.
+ */
+fn sprites_on_line(mmu: &mmu::MMU, line: u8, height: i16) -> Vec<Sprite> {
+    let ly = line as i16;
 
-        let row = tile.get_row(pixel_index_y);
+    let mut sprites = (0..40)
+        .map(|i| mmu.object_attribute_memory.get_sprite(i))
+        .filter(|s| ly >= s.y && ly < s.y + height)
+        .take(10)
+        .collect::<Vec<Sprite>>();
 
-        for j in (pixel_index_x..8).rev() {
-            let frame_index = (mmu.hardware_io.lcd_line_count.get() as u32 * 160) + i as u32;
-            framebuffer[frame_index as usize] = mmu.hardware_io.background_palette.map_shades(row[j as usize]);
-            i += 1;
+    // sort_by_key is stable, so OAM order is kept for sprites with the same X
+    sprites.sort_by_key(|s| s.x);
+    sprites
+}
+
+fn render_sprites(
+    mmu: &mmu::MMU,
+    background: &[u8; 160],
+    framebuffer: &mut framebuffer::Framebuffer,
+) {
+    let line = mmu.hardware_io.lcd_line_count.get();
+    let tall = mmu.hardware_io
+        .lcd_control_register
+        .get_flag(LCDControlFlag::ObjectSize);
+    let height = if tall { 16 } else { 8 };
+
+    let sprites = sprites_on_line(mmu, line, height);
+
+    for x in 0..160i16 {
+        for s in sprites.iter() {
+            if x < s.x || x >= s.x + 8 {
+                continue;
+            }
+
+            let px = if s.x_flip { 7 - (x - s.x) } else { x - s.x };
+            let py = if s.y_flip {
+                height - 1 - (line as i16 - s.y)
+            } else {
+                line as i16 - s.y
+            };
+
+            /* 8x16 sprites use a pair of tiles, the lowest bit of the tile index is ignored */
+            let tile_index = if tall {
+                (s.tile & 0xFE) + (py / 8) as u8
+            } else {
+                s.tile
+            };
+
+            let tile = mmu.tile_data_1.get_tile(tile_index);
+            let colour = tile.get_row((py % 8) as u8)[px as usize];
+
+            /* Colour 0 is transparent, so a sprite further down the priority list can show
+             * through.
+             */
+            if colour == 0 {
+                continue;
+            }
+
+            /* The highest priority opaque sprite pixel decides this pixel, even when it's hidden
+             * behind the background lower priority sprites don't get to draw.
+             */
+            if !(s.behind_background && background[x as usize] != 0) {
+                let palette = if s.use_palette_1 {
+                    &mmu.hardware_io.object_palette_2
+                } else {
+                    &mmu.hardware_io.object_palette_1
+                };
+
+                let frame_index = (line as u32 * 160) + x as u32;
+                framebuffer[frame_index as usize] = palette.map_shades(colour);
+            }
+            break;
+        }
+    }
+}
+
+fn render_line(mmu: &mmu::MMU, window_line: &mut u8, framebuffer: &mut framebuffer::Framebuffer) {
+    let lcd_control = &mmu.hardware_io.lcd_control_register;
+
+    /* Colour indices of the background and window for this line, with the background switched
+     * off the line is blank (colour 0) and the window is hidden as well.
+     */
+    let mut background = [0; 160];
+
+    if lcd_control.get_flag(LCDControlFlag::BackgroundDisplay) {
+        render_background(mmu, &mut background);
+
+        if lcd_control.get_flag(LCDControlFlag::WindowDisplayEnable)
+            && render_window(mmu, *window_line, &mut background)
+        {
+            *window_line += 1;
         }
     }
+
+    let line_start = mmu.hardware_io.lcd_line_count.get() as usize * 160;
+    for (i, colour) in background.iter().enumerate() {
+        framebuffer[line_start + i] = mmu.hardware_io.background_palette.map_shades(*colour);
+    }
+
+    if lcd_control.get_flag(LCDControlFlag::ObjectDisplayEnable) {
+        render_sprites(mmu, &background, framebuffer);
+    }
 }
 
 impl GPU {
-    // pub fn new_frame_available(&self) -> bool {
-    //     self.frame_available
-    // }
+    /* True once per frame, when the PPU has drawn the last visible line and entered VBlank. Reading
+     * it clears it.
+     */
+    pub fn take_frame(&mut self) -> bool {
+        let frame_available = self.frame_available;
+        self.frame_available = false;
+        frame_available
+    }
+
+    /* Mirrors the PPU state into STAT and requests the LCDStat interrupt.
+     *
+     * All of the enabled STAT sources are ORed onto a single interrupt line and the interrupt is
+     * only requested when that line goes from low to high. So if a source is still holding the
+     * line high when another one becomes active (e.g. the LY=LYC coincidence running into
+     * HBlank) no second interrupt is requested, that's the "STAT IRQ blocking" games have to
+     * work around.
+     *
+     * On the DMG the OAM source also raises the line on line 144 as the PPU enters VBlank, as if
+     * it were starting another OAM search.
+     */
+    fn update_status(&mut self, mmu: &mut mmu::MMU) {
+        let line_count = mmu.hardware_io.lcd_line_count.get();
+        let coincidence = line_count == mmu.hardware_io.lcd_line_compare;
+
+        let status = &mut mmu.hardware_io.lcd_status_register;
+
+        status.set_mode(match self.mode {
+            Mode::HBlank => LCDModes::HBlank,
+            Mode::VBlank => LCDModes::VBlank,
+            Mode::OAM => LCDModes::Searching,
+            Mode::VRAM => LCDModes::Transfer,
+        });
+        status.set_flag(LCDStatusFlag::Coincidence, coincidence);
+
+        let line = (coincidence && status.get_flag(LCDStatusFlag::LYCoincidence))
+            || (self.mode == Mode::HBlank && status.get_flag(LCDStatusFlag::HBlank))
+            || (self.mode == Mode::VBlank && status.get_flag(LCDStatusFlag::VBlank))
+            || (self.mode == Mode::OAM && status.get_flag(LCDStatusFlag::OAM))
+            || (self.mode == Mode::VBlank && line_count == 144 && status.get_flag(LCDStatusFlag::OAM));
+
+        if line && !self.stat_line {
+            mmu.hardware_io.interrupts.request(Flag::LCDStat);
+        }
+
+        self.stat_line = line;
+    }
 
     pub fn tick(
         &mut self,
@@ -115,6 +292,30 @@ impl GPU {
         cycles: u8,
         framebuffer: &mut framebuffer::Framebuffer,
     ) {
+        /* With the LCD off the PPU sits at the start of line 0 in mode 0, it picks up again from
+         * the start of a frame when the LCD is switched back on.
+         */
+        if !mmu.hardware_io
+            .lcd_control_register
+            .get_flag(LCDControlFlag::LCDDisplayEnable)
+        {
+            if self.enabled {
+                self.enabled = false;
+                self.mode_clock = 0;
+                self.window_line = 0;
+                self.mode = Mode::HBlank;
+                mmu.hardware_io.lcd_line_count.set(0);
+                mmu.hardware_io.lcd_status_register.set_mode(LCDModes::HBlank);
+                self.stat_line = false;
+            }
+            return;
+        }
+
+        if !self.enabled {
+            self.enabled = true;
+            self.mode = Mode::OAM;
+        }
+
         self.mode_clock += cycles as u32;
 
         match self.mode {
@@ -125,7 +326,7 @@ impl GPU {
             }
             Mode::VRAM => {
                 if self.mode_clock >= 252 {
-                    render_line(&*mmu, framebuffer);
+                    render_line(&*mmu, &mut self.window_line, framebuffer);
                     self.mode = Mode::HBlank;
                 }
             }
@@ -136,6 +337,7 @@ impl GPU {
                     mmu.hardware_io.lcd_line_count.inc();
 
                     if mmu.hardware_io.lcd_line_count.get() == 144 {
+                        mmu.hardware_io.interrupts.request(Flag::VBlank);
                         self.frame_available = true;
                         self.mode = Mode::VBlank;
                     } else {
@@ -143,20 +345,24 @@ impl GPU {
                     }
                 }
             }
+            /* VBlank lasts for 10 full lines, 144-153, and the count wraps back to 0 at the end of
+             * line 153.
+             */
             Mode::VBlank => {
-                self.frame_available = false;
-
                 if self.mode_clock >= 456 {
                     self.mode_clock -= 456;
+
                     mmu.hardware_io.lcd_line_count.inc();
-                }
 
-                if mmu.hardware_io.lcd_line_count.get() == 153 {
-                    mmu.hardware_io.lcd_line_count.set(0);
-                    self.mode = Mode::OAM;
+                    if mmu.hardware_io.lcd_line_count.get() == 0 {
+                        self.window_line = 0;
+                        self.mode = Mode::OAM;
+                    }
                 }
             }
         }
+
+        self.update_status(mmu);
     }
 }
 
@@ -170,3 +376,161 @@ impl GPU {
  * Bit 1: OBJ (Sprite) Display Enable    (0=Off, 1=On)
  * Bit 0: BG Display                     (0=Off, 1=On)
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::Device;
+    use device::boot_rom;
+    use device::cartridge;
+    use palette;
+
+    /* the tile number doubles as a marker of which OAM entry a sprite came from */
+    fn set_sprite(mmu: &mut mmu::MMU, index: u16, y: u8, x: u8) {
+        mmu.object_attribute_memory.set(index * 4, y + 16);
+        mmu.object_attribute_memory.set(index * 4 + 1, x + 8);
+        mmu.object_attribute_memory.set(index * 4 + 2, index as u8);
+    }
+
+    #[test]
+    fn test_sprites_on_line_limit_and_priority() {
+        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+
+        // 12 sprites on line 0 with decreasing X, only the first 10 in OAM count
+        for i in 0..12 {
+            set_sprite(&mut mmu, i, 0, 100 - i as u8);
+        }
+        // a sprite with the same X as sprite 0 later in OAM loses to it
+        set_sprite(&mut mmu, 0, 0, 50);
+        set_sprite(&mut mmu, 5, 0, 50);
+        // a sprite not on the line
+        set_sprite(&mut mmu, 12, 20, 0);
+
+        let sprites = sprites_on_line(&mmu, 0, 8);
+        let order = sprites.iter().map(|s| s.tile).collect::<Vec<u8>>();
+
+        assert_eq!(order, vec![0, 5, 9, 8, 7, 6, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_stat_interrupt_blocking() {
+        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+        let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+        let mut gpu = new();
+
+        // LY=LYC on line 0 and HBlank both enabled, the coincidence holds the line high into
+        // HBlank so only one interrupt is requested for line 0
+        mmu.hardware_io.lcd_control_register.set(0x80);
+        mmu.hardware_io.lcd_line_compare = 0;
+        mmu.hardware_io.lcd_status_register.set(0x48);
+
+        gpu.tick(&mut mmu, 4, &mut framebuffer);
+        assert_eq!(mmu.hardware_io.lcd_status_register.get(), 0x80 | 0x48 | 0x04 | 0x02);
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x02);
+        mmu.hardware_io.interrupts.set(0);
+
+        while gpu.mode != Mode::HBlank {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x00);
+
+        // on line 1 the coincidence is gone so HBlank raises the line again
+        while mmu.hardware_io.lcd_line_count.get() == 0 {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        while gpu.mode != Mode::HBlank {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        assert_eq!(mmu.hardware_io.lcd_status_register.get() & 0x07, 0x00);
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x02);
+    }
+
+    #[test]
+    fn test_stat_oam_source_on_vblank() {
+        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+        let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+        let mut gpu = new();
+
+        // only the OAM source enabled
+        mmu.hardware_io.lcd_control_register.set(0x80);
+        mmu.hardware_io.lcd_line_compare = 0xFF;
+        mmu.hardware_io.lcd_status_register.set(0x20);
+
+        while mmu.hardware_io.lcd_line_count.get() != 143 || gpu.mode != Mode::HBlank {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        mmu.hardware_io.interrupts.set(0);
+
+        // entering VBlank raises it along with VBlank itself
+        while gpu.mode != Mode::VBlank {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x03);
+        mmu.hardware_io.interrupts.set(0);
+
+        // but not on the rest of the VBlank lines
+        while mmu.hardware_io.lcd_line_count.get() != 153 {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+        }
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x00);
+    }
+
+    #[test]
+    fn test_vblank_interrupt_and_frame_timing() {
+        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+        let mut framebuffer: framebuffer::Framebuffer = [palette::Shade::White; 23040];
+        let mut gpu = new();
+
+        mmu.hardware_io.lcd_control_register.set(0x80);
+
+        let mut cycles = 0;
+        while !gpu.take_frame() {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+            cycles += 4;
+        }
+        assert_eq!(cycles, 144 * 456);
+        assert_eq!(mmu.hardware_io.lcd_line_count.get(), 144);
+        assert_eq!(mmu.hardware_io.interrupts.get(), 0x01);
+
+        // the next frame comes a full 154 lines later
+        cycles = 0;
+        while !gpu.take_frame() {
+            gpu.tick(&mut mmu, 4, &mut framebuffer);
+            cycles += 4;
+        }
+        assert_eq!(cycles, 70224);
+
+        // switching the LCD off resets LY
+        mmu.hardware_io.lcd_control_register.set(0x00);
+        gpu.tick(&mut mmu, 4, &mut framebuffer);
+        assert_eq!(mmu.hardware_io.lcd_line_count.get(), 0);
+        assert_eq!(mmu.hardware_io.lcd_status_register.get() & 0x03, 0x00);
+    }
+
+    #[test]
+    fn test_render_window() {
+        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+
+        // tile 0 is solid colour 3 and the window map is all tile 0
+        for a in 0x8000..0x8010 {
+            mmu.set(a, 0xFF);
+        }
+        mmu.hardware_io.lcd_control_register.set(0x10);
+        mmu.hardware_io.window_position_x = 7 + 80;
+        mmu.hardware_io.window_position_y = 0;
+
+        let mut line = [0; 160];
+        assert!(render_window(&mmu, 0, &mut line));
+        assert!(line[..80].iter().all(|&p| p == 0));
+        assert!(line[80..].iter().all(|&p| p == 3));
+
+        // WX past the right edge of the screen hides the window
+        mmu.hardware_io.window_position_x = 167;
+        assert!(!render_window(&mmu, 0, &mut [0; 160]));
+
+        // and so does a WY below the current line
+        mmu.hardware_io.window_position_x = 7;
+        mmu.hardware_io.window_position_y = 1;
+        assert!(!render_window(&mmu, 0, &mut [0; 160]));
+    }
+}
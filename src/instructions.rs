@@ -1,5 +1,5 @@
+use bus;
 use bytes;
-use mmu;
 
 use registers::Registers8;
 use registers::Registers16;
@@ -30,7 +30,8 @@ pub enum Destination16 {
 
 #[derive(Debug, Clone, Copy)]
 pub enum ADD16Args {
-    R(Registers16)
+    R(Registers16),
+    N,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -64,6 +65,8 @@ pub enum LoadFF00Targets {
 #[derive(Debug, Clone, Copy)]
 pub enum Op {
     NotImplemented,
+    /* The opcodes the SM83 doesn't have, fetching one locks the cpu up */
+    Illegal,
     STOP,
     NOP,
     DI, // Disable interrupts
@@ -84,6 +87,7 @@ pub enum Op {
     ADD16(ADD16Args, ADD16Args),
 
     RLCA,
+    RLA,
     RRCA,
     RRA,
     DAA,
@@ -96,6 +100,7 @@ pub enum Op {
     LoadAndIncR,
     LoadAndDec,
     LoadAndDecR,
+    LoadHLSP,
     JR(JrArgs),
     JP(JpArgs),
     LoadFF00(LoadFF00Targets, LoadFF00Targets),
@@ -148,8 +153,9 @@ fn rst_jump_location(r:&RstArgs) -> u16 {
     }
 }
 
-fn rst(registers: &mut Registers, mmu: &mut mmu::MMU, r:&RstArgs)  {
-    push(registers, mmu, &Registers16::PC);
+fn rst(registers: &mut Registers, bus: &mut bus::Bus, r:&RstArgs)  {
+    bus.idle();
+    push(registers, bus, &Registers16::PC);
     jump(registers, rst_jump_location(r));
 
 }
@@ -157,10 +163,8 @@ fn rst(registers: &mut Registers, mmu: &mut mmu::MMU, r:&RstArgs)  {
 fn cpl(registers: &mut Registers, v:u8) -> u8 {
     let out = !v;
 
-    registers.set_flag(Flag::Z, false);
     registers.set_flag(Flag::N, true);
     registers.set_flag(Flag::H, true);
-    registers.set_flag(Flag::C, false);
 
     out
 }
@@ -200,13 +204,7 @@ fn swap(registers:&mut Registers, v: u8) -> u8 {
 /* shift right but retain original value
  */
 fn sra(registers:&mut Registers, v: u8) -> u8 {
-    let c = bytes::check_bit(v, 0);
-
-    let mut out = v >> 1;
-    
-    if c {
-        out = out | 0x80;
-    }
+    let out = (v >> 1) | (v & 0x80);
 
     registers.set_flag(Flag::Z, out == 0);
     registers.set_flag(Flag::N, false);
@@ -232,7 +230,7 @@ fn srl(registers:&mut Registers, v: u8) -> u8 {
 fn rr(registers: &mut Registers, v: u8) -> u8 {
     let c = registers.get_flag(Flag::C);
 
-    let out = if c { (v >> 1) | 0xFF } else { v >> 1 };
+    let out = if c { (v >> 1) | 0x80 } else { v >> 1 };
 
     registers.set_flag(Flag::Z, out == 0);
     registers.set_flag(Flag::N, false);
@@ -245,7 +243,7 @@ fn rr(registers: &mut Registers, v: u8) -> u8 {
 /* Rotate n right. Old bit 0 to Carry flag.
  */
 fn rrc(registers: &mut Registers, v: u8) -> u8 {
-    let out = v >> 1;
+    let out = v.rotate_right(1);
 
     registers.set_flag(Flag::Z, out == 0);
     registers.set_flag(Flag::N, false);
@@ -258,7 +256,7 @@ fn rrc(registers: &mut Registers, v: u8) -> u8 {
 /* Rotate n left. Old bit 7 to Carry flag
  */
 fn rlc(registers: &mut Registers, v: u8) -> u8 {
-    let out = v << 1;
+    let out = v.rotate_left(1);
 
     registers.set_flag(Flag::Z, out == 0);
     registers.set_flag(Flag::N, false);
@@ -332,13 +330,15 @@ fn and(registers: &mut Registers, v:u8) {
     registers.set8(&Registers8::A, n);
 }
 
-fn call(registers: &mut Registers, mmu: &mut mmu::MMU, v: u16) {
-    push(registers, mmu, &Registers16::PC);
+fn call(registers: &mut Registers, bus: &mut bus::Bus, v: u16) {
+    bus.idle();
+    push(registers, bus, &Registers16::PC);
     registers.set16( &Registers16::PC, v);
 }
 
-fn ret(registers: &mut Registers, mmu: &mmu::MMU) {
-    pop(registers, mmu, &Registers16::PC);
+fn ret(registers: &mut Registers, bus: &mut bus::Bus) {
+    pop(registers, bus, &Registers16::PC);
+    bus.idle();
 }
 
 fn dec8(registers: &mut Registers, a: u8) -> u8 {
@@ -398,19 +398,16 @@ fn sub(registers: &mut Registers, a: u8, v: u8) -> u8 {
     n
 }
 
+/* The carry in counts towards both the half carry and the carry out */
 fn sbc(registers: &mut Registers, a: u8, v: u8) -> u8 {
-    let c = registers.get_flag(Flag::C);
+    let c = registers.get_flag(Flag::C) as u8;
 
-    let n = if c {
-        a.wrapping_sub(v).wrapping_sub(1)
-    } else {
-        a.wrapping_sub(v)
-    };
+    let n = a.wrapping_sub(v).wrapping_sub(c);
 
     registers.set_flag(Flag::Z, n == 0);
     registers.set_flag(Flag::N, true);
-    registers.set_flag(Flag::H, check_half_carry_sub_8(a, v));
-    registers.set_flag(Flag::C, n > a);
+    registers.set_flag(Flag::H, (a & 0x0F) < (v & 0x0F) + c);
+    registers.set_flag(Flag::C, (a as u16) < (v as u16) + (c as u16));
 
     n
 }
@@ -449,18 +446,14 @@ fn add(registers: &mut Registers, a: u8, v: u8) -> u8 {
 }
 
 fn adc(registers: &mut Registers, a: u8, v: u8) -> u8 {
-    let c = registers.get_flag(Flag::C);
+    let c = registers.get_flag(Flag::C) as u8;
 
-    let n = if c {
-        a.wrapping_add(v).wrapping_add(1)
-    } else {
-        a.wrapping_add(v)
-    };
+    let n = a.wrapping_add(v).wrapping_add(c);
 
     registers.set_flag(Flag::Z, n == 0);
     registers.set_flag(Flag::N, false);
-    registers.set_flag(Flag::H, check_half_carry_add_8(a, v));
-    registers.set_flag(Flag::C, n < a);
+    registers.set_flag(Flag::H, (a & 0x0F) + (v & 0x0F) + c > 0x0F);
+    registers.set_flag(Flag::C, (a as u16) + (v as u16) + (c as u16) > 0xFF);
 
     n
 }
@@ -470,7 +463,6 @@ fn add16(registers: &mut Registers, destination: &Registers16, v: u16) {
     let a = registers.get16(destination);
     let n = a.wrapping_add(v);
 
-    registers.set_flag(Flag::Z, n == 0);
     registers.set_flag(Flag::N, false);
     registers.set_flag(Flag::H, check_half_carry_add_16(a, v));
     registers.set_flag(Flag::C, n < a);
@@ -478,48 +470,110 @@ fn add16(registers: &mut Registers, destination: &Registers16, v: u16) {
     registers.set16(destination, n);
 }
 
+/* SP plus a signed byte, used by ADD SP,e and LD HL,SP+e. The flags come from adding the byte
+ * unsigned to the low byte of SP.
+ */
+fn add_sp_signed(registers: &mut Registers, v: u8) -> u16 {
+    let sp = registers.get16(&Registers16::SP);
+
+    registers.set_flag(Flag::Z, false);
+    registers.set_flag(Flag::N, false);
+    registers.set_flag(Flag::H, (sp & 0x0F) + (v as u16 & 0x0F) > 0x0F);
+    registers.set_flag(Flag::C, (sp & 0xFF) + (v as u16) > 0xFF);
+
+    bytes::add_unsigned_signed(sp, v as i8)
+}
+
+/* Corrects A back to binary coded decimal after an addition or subtraction of two BCD numbers,
+ * using N to tell which it was and H and C for the digits that carried.
+ */
+fn daa(registers: &mut Registers) {
+    let a = registers.get8(&Registers8::A);
+    let mut carry = registers.get_flag(Flag::C);
+    let mut adjust = 0;
+
+    let out = if registers.get_flag(Flag::N) {
+        if registers.get_flag(Flag::H) {
+            adjust |= 0x06;
+        }
+        if carry {
+            adjust |= 0x60;
+        }
+        a.wrapping_sub(adjust)
+    } else {
+        if registers.get_flag(Flag::H) || a & 0x0F > 0x09 {
+            adjust |= 0x06;
+        }
+        if carry || a > 0x99 {
+            adjust |= 0x60;
+            carry = true;
+        }
+        a.wrapping_add(adjust)
+    };
+
+    registers.set_flag(Flag::Z, out == 0);
+    registers.set_flag(Flag::H, false);
+    registers.set_flag(Flag::C, carry);
+
+    registers.set8(&Registers8::A, out);
+}
+
+/* The rotates of A that don't go through the CB prefix always clear Z */
+fn rotate_a(registers: &mut Registers, f: fn(&mut Registers, u8) -> u8) {
+    let v = registers.get8(&Registers8::A);
+    let out = f(registers, v);
+    registers.set_flag(Flag::Z, false);
+    registers.set8(&Registers8::A, out);
+}
+
 fn load_to_memory(
     registers: &mut Registers,
-    mmu: &mut mmu::MMU,
+    bus: &mut bus::Bus,
     rm: &Registers16,
     rv: &Registers8,
 ) {
     let m = registers.get16(rm);
     let v = registers.get8(rv);
-    mmu.set(m, v);
+    bus.set(m, v);
 }
 
 fn load_from_memory(
     registers: &mut Registers,
-    mmu: &mut mmu::MMU,
+    bus: &mut bus::Bus,
     rv: &Registers8,
     rm: &Registers16,
 ) {
     let m = registers.get16(rm);
-    let v = mmu.get(m);
+    let v = bus.get(m);
     registers.set8(rv, v);
 }
 
-pub fn push(registers: &mut Registers, mmu: &mut mmu::MMU, r: &Registers16) {
+/* Pushes take two M-cycles for the writes, the high byte goes first. Every push is preceded by
+ * an idle M-cycle but that's left to the caller as it's not always right before the writes.
+ */
+pub fn push(registers: &mut Registers, bus: &mut bus::Bus, r: &Registers16) {
     let sp = registers.get16(&Registers16::SP);
     let v = registers.get16(r);
     let (vh, vl) = bytes::split_u16(v);
-    mmu.set(sp - 1, vh);
-    mmu.set(sp - 2, vl);
-    registers.set16(&Registers16::SP, sp - 2);
+    bus.set(sp.wrapping_sub(1), vh);
+    bus.set(sp.wrapping_sub(2), vl);
+    registers.set16(&Registers16::SP, sp.wrapping_sub(2));
 }
 
-fn pop(registers: &mut Registers, mmu: &mmu::MMU, r: &Registers16) {
+fn pop(registers: &mut Registers, bus: &mut bus::Bus, r: &Registers16) {
     let sp = registers.get16(&Registers16::SP);
-    let v = mmu.get16(sp);
+    let vl = bus.get(sp);
+    let vh = bus.get(sp.wrapping_add(1));
+    let v = bytes::combine_little(vl, vh);
     registers.set16(r, v);
-    registers.set16(&Registers16::SP, sp + 2);
+    registers.set16(&Registers16::SP, sp.wrapping_add(2));
 }
 
 impl Op {
     pub fn args(&self) -> u8 {
         match self {
             Op::NotImplemented => 0,
+            Op::Illegal => 0,
             Op::STOP => 1,
             Op::NOP => 0,
             Op::DI => 0,
@@ -556,8 +610,11 @@ impl Op {
             Op::ADD(_) => 0,
             Op::ADC(Destination8::N) => 1,
             Op::ADC(_) => 0,
+            Op::ADD16(_, ADD16Args::N) => 1,
             Op::ADD16(_, _) => 0,
+            Op::LoadHLSP => 1,
             Op::JR(_) => 1,
+            Op::JP(JpArgs::HL) => 0,
             Op::JP(_) => 2,
             Op::LoadFF00(_, LoadFF00Targets::N) => 1,
             Op::LoadFF00(LoadFF00Targets::N, _) => 1,
@@ -571,6 +628,7 @@ impl Op {
             Op::CP(Destination8::N) => 1,
             Op::CP(_) => 0,
             Op::RLCA => 0,
+            Op::RLA => 0,
             Op::RRCA => 0,
             Op::RRA => 0,
             Op::DAA => 0,
@@ -598,213 +656,174 @@ impl Op {
     pub fn call(
         &self,
         registers: &mut Registers,
-        mmu: &mut mmu::MMU,
+        bus: &mut bus::Bus,
         args: &Vec<u8>,
-    ) -> u8 {
+    ) {
         match self {
-            Op::NotImplemented => 0,
-            Op::STOP => {
-                println!("stopping!");
-                4
-            },
-            Op::NOP => 4,
-            Op::DI => {
-                registers.set_interrupts_enabled(false);
-                4
-            }
-            Op::EI => {
-                registers.set_interrupts_enabled(true);
-                4
-            }
-            Op::HALT => {
-                println!("HALTing!");
-                4
-            },
-            Op::PrefixCB => 4,
+            Op::NotImplemented => {}
+            Op::Illegal => {}
+            Op::STOP => {}
+            Op::NOP => {}
+            Op::DI => {}
+            Op::EI => {}
+            Op::HALT => {}
+            Op::PrefixCB => {}
             Op::LD8(Destination8::R(r1), Destination8::R(r2)) => {
                 let v = registers.get8(r2);
                 registers.set8(r1, v);
-                4
             }
             Op::LD8(Destination8::R(r1), Destination8::N) => {
                 registers.set8(r1, args[0]);
-                4
             }
             Op::LD8(Destination8::R(r1), Destination8::Mem(r2)) => {
-                let v = mmu.get(registers.get16(r2));
+                let v = bus.get(registers.get16(r2));
                 registers.set8(r1, v);
-                8
             }
             Op::LD8(Destination8::Mem(r1), Destination8::R(r2)) => {
-                load_to_memory(registers, mmu, r1, r2);
-                8
+                load_to_memory(registers, bus, r1, r2);
             }
             Op::LD8(Destination8::N, Destination8::R(r)) => {
                 let a = bytes::combine_little(args[0], args[1]);
                 let v = registers.get8(r);
-                mmu.set(a, v);
-                16
+                bus.set(a, v);
             }
             Op::LD8(Destination8::Mem(r1), Destination8::N) => {
                 let v = args[0];
                 let rm = registers.get16(r1);
-                mmu.set(rm, v);
-                12
+                bus.set(rm, v);
             }
             Op::LD8(Destination8::R(r), Destination8::MemAtN) => {
                 let a = bytes::combine_little(args[0], args[1]);
-                let v = mmu.get(a);
+                let v = bus.get(a);
 
                 registers.set8(r, v);
-                16
             }
             Op::LD8(_, _) => panic!("Not Implemented"),
 
             Op::LD16(Destination16::R(r1), Destination16::R(r2)) => {
                 let v = registers.get16(r2);
                 registers.set16(r1, v);
-                12
+                bus.idle();
             }
             Op::LD16(Destination16::R(r1), Destination16::N) => {
                 let v = bytes::combine_little(args[0], args[1]);
                 registers.set16(r1, v);
-                12
             }
             Op::LD16(Destination16::N, Destination16::R(r)) => {
                 let v = registers.get16(r);
                 let (v_high, v_low) = bytes::split_u16(v);
                 let a = bytes::combine_little(args[0], args[1]);
-                mmu.set(a, v_high);
-                mmu.set(a+1, v_low);
-                20
+                bus.set(a, v_low);
+                bus.set(a.wrapping_add(1), v_high);
             }
             Op::LD16(Destination16::Mem(_), _) => {
                 panic!("Not Implemented");
-                //20
             }
             Op::LD16(_, _) => panic!("Not Implemented"),
 
             Op::LoadFF00(LoadFF00Targets::C, LoadFF00Targets::A) => {
                 let c = registers.get8(&Registers8::C) as u16;
                 let a = registers.get8(&Registers8::A);
-                mmu.set(c + 0xFF00, a);
-                8
+                bus.set(c + 0xFF00, a);
             }
             Op::LoadFF00(LoadFF00Targets::A, LoadFF00Targets::C) => {
                 let c = registers.get8(&Registers8::C) as u16;
-                let v = mmu.get(c + 0xFF00);
+                let v = bus.get(c + 0xFF00);
                 registers.set8(&Registers8::A, v);
-                8
             }
             Op::LoadFF00(LoadFF00Targets::A, LoadFF00Targets::N) => {
                 let ma = args[0] as u16;
-                let v = mmu.get(ma + 0xFF00);
+                let v = bus.get(ma + 0xFF00);
                 registers.set8(&Registers8::A, v);
-                8
             }
             Op::LoadFF00(LoadFF00Targets::N, LoadFF00Targets::A) => {
                 let a = registers.get8(&Registers8::A);
                 let ma = args[0] as u16;
-                mmu.set(ma + 0xFF00, a);
-                8
+                bus.set(ma + 0xFF00, a);
             }
             Op::LoadFF00(_, _) => panic!("invalid loadFF00 inputs"),
 
             Op::LoadAndDec => {
-                load_to_memory(registers, mmu, &Registers16::HL, &Registers8::A);
+                load_to_memory(registers, bus, &Registers16::HL, &Registers8::A);
                 registers.dec_hl();
-                8
             }
             Op::LoadAndDecR => {
-                load_from_memory(registers, mmu, &Registers8::A, &Registers16::HL);
+                load_from_memory(registers, bus, &Registers8::A, &Registers16::HL);
                 registers.dec_hl();
-                8
             }
             Op::LoadAndInc => {
-                load_to_memory(registers, mmu, &Registers16::HL, &Registers8::A);
+                load_to_memory(registers, bus, &Registers16::HL, &Registers8::A);
                 registers.inc_hl();
-                8
             }
             Op::LoadAndIncR => {
-                load_from_memory(registers, mmu, &Registers8::A, &Registers16::HL);
+                load_from_memory(registers, bus, &Registers8::A, &Registers16::HL);
                 registers.inc_hl();
-                8
             }
+            Op::LoadHLSP => {
+                let v = add_sp_signed(registers, args[0]);
+                registers.set16(&Registers16::HL, v);
+                bus.idle();
+            }
+            /* taking a jump costs an extra M-cycle to load PC */
             Op::JR(JrArgs::CheckFlag(f)) => {
                 if check_flags(registers, f) {
                     jump_relative(registers, args[0] as i8);
-                    16
-                } else {
-                    12
+                    bus.idle();
                 }
             }
             Op::JR(JrArgs::N) => {
                 jump_relative(registers, args[0] as i8);
-                12
+                bus.idle();
             }
 
             Op::JP(JpArgs::CheckFlag(f)) => {
                 if check_flags(registers, f) {
                     jump(registers, bytes::combine_little(args[0], args[1]));
-                    16
-                } else {
-                    12
+                    bus.idle();
                 }
             }
             Op::JP(JpArgs::N) => {
                 jump(registers, bytes::combine_little(args[0], args[1]));
-                16
+                bus.idle();
             }
             Op::JP(JpArgs::HL) => {
-                let m = registers.get16(&Registers16::HL);
-                let v = mmu.get16(m);
+                let v = registers.get16(&Registers16::HL);
                 jump(registers, v);
-                16
             }
 
             Op::CALL(None) => {
-                call(registers, mmu, bytes::combine_little(args[0], args[1]));
-                24
+                call(registers, bus, bytes::combine_little(args[0], args[1]));
             }
 
             Op::CALL(Some(flag)) => {
                 if check_flags(registers, &flag) {
-                    call(registers, mmu, bytes::combine_little(args[0], args[1]));
-                    24
-                } else {
-                    12
+                    call(registers, bus, bytes::combine_little(args[0], args[1]));
                 }
             }
 
             Op::PUSH(r) => {
-                push(registers, mmu, r);
-                16
+                bus.idle();
+                push(registers, bus, r);
             }
             Op::POP(r) => {
-                pop(registers, mmu, r);
-                12
+                pop(registers, bus, r);
             }
             Op::RET(None) => {
-                ret(registers, mmu);
-                16
+                ret(registers, bus);
             }
+            /* checking the flag takes an M-cycle of its own */
             Op::RET(Some(f)) => {
+                bus.idle();
                 if check_flags(registers, f) {
-                    ret(registers, mmu);
-                    20
-                } else {
-                    8
+                    ret(registers, bus);
                 }
-
             }
             Op::RST(r) => {
-                rst(registers, mmu, r);
-                32
+                rst(registers, bus, r);
             }
             Op::RETI => {
-                ret(registers, mmu);
+                ret(registers, bus);
                 registers.set_interrupts_enabled(true);
-                16
             }
 
             // ALU Codes
@@ -814,14 +833,12 @@ impl Op {
                 let out = inc8(registers, v);
 
                 registers.set8(r, out);
-                4
             }
             Op::INC8(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = inc8(registers, v);
-                mmu.set(rm, out);
-                12
+                bus.set(rm, out);
             }
             Op::INC8(Destination8::N) => panic!("Not Implemented"),
             Op::INC8(Destination8::MemAtN) => panic!("Not Implemented"),
@@ -831,7 +848,7 @@ impl Op {
                 let n = v.wrapping_add(1);
 
                 registers.set16(r, n);
-                8
+                bus.idle();
             }
             Op::INC16(Destination16::Mem(_)) => panic!("Not Implemented"),
             Op::INC16(Destination16::N) => panic!("Not Implemented"),
@@ -841,14 +858,12 @@ impl Op {
                 let n = dec8(registers, v);
 
                 registers.set8(r, n);
-                4
             }
             Op::DEC8(Destination8::Mem(r)) => {
                 let a = registers.get16(r);
-                let v = mmu.get(a);
+                let v = bus.get(a);
                 let n = dec8(registers, v);
-                mmu.set(a, n);
-                12
+                bus.set(a, n);
             }
             Op::DEC8(Destination8::N) => panic!("Not Implemented"),
             Op::DEC8(Destination8::MemAtN) => panic!("Not Implemented"),
@@ -857,68 +872,64 @@ impl Op {
                 let n = dec16(registers, v);
 
                 registers.set16(r, n);
-                8
+                bus.idle();
             }
             Op::DEC16(Destination16::Mem(_)) => panic!("Not Implemented"),
             Op::DEC16(Destination16::N) => panic!("Not Implemented"),
 
             Op::CP(Destination8::N) => {
                 compare(registers, args[0]);
-                8
             }
             Op::CP(Destination8::R(r)) => {
                 let v = registers.get8(r);
                 compare(registers, v);
-                4
             }
             Op::CP(Destination8::Mem(r)) => {
                 let m = registers.get16(r);
-                let v = mmu.get(m);
+                let v = bus.get(m);
                 compare(registers, v);
-                8
             }
             Op::CP(Destination8::MemAtN) => panic!("Not Implemented"),
 
             Op::AND(Destination8::R(r)) => {
                 let v = registers.get8(r);
                 and(registers,v);
-                4
             }
-            Op::AND(Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::AND(Destination8::Mem(r)) => {
+                let rm = registers.get16(r);
+                let v = bus.get(rm);
+                and(registers, v);
+            }
             Op::AND(Destination8::N) => {
                 and(registers, args[0]);
-                8
             }
             Op::AND(Destination8::MemAtN) => panic!("Not Implemented"),
 
             Op::OR(Destination8::R(r)) => {
                 let v = registers.get8(r);
                 or(registers, v);
-                4
             }
             Op::OR(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 or(registers, v);
-                8
             }
-            Op::OR(Destination8::N) => panic!("Not Implemented"),
+            Op::OR(Destination8::N) => {
+                or(registers, args[0]);
+            }
             Op::OR(Destination8::MemAtN) => panic!("Not Implemented"),
 
             Op::XOR(Destination8::R(r)) => {
                 let v = registers.get8(r);
                 xor(registers, v);
-                4
             }
             Op::XOR(Destination8::Mem(r)) => {
                 let m = registers.get16(r);
-                let v = mmu.get(m);
+                let v = bus.get(m);
                 xor(registers, v);
-                8
             }
             Op::XOR(Destination8::N) => {
                 xor(registers, args[0]);
-                8
             }
             Op::XOR(Destination8::MemAtN) => panic!("Not Implemented"),
 
@@ -932,17 +943,15 @@ impl Op {
                 let out = sbc(registers, a, v);
 
                 registers.set8(ra, out);
-                4
             }
             Op::SBC(Destination8::Mem(r)) => {
                 let ra = &Registers8::A;
                 let a = registers.get8(ra);
                 let mr = registers.get16(r);
-                let v = mmu.get(mr);
+                let v = bus.get(mr);
                 let out = sbc(registers, a, v);
 
                 registers.set8(ra, out);
-                8
             }
             Op::SBC(Destination8::N) => {
                 let ra = &Registers8::A;
@@ -950,7 +959,6 @@ impl Op {
                 let v = args[0];
                 let out = sbc(registers, a, v);
                 registers.set8(ra, out);
-                4
             }
             Op::SBC(Destination8::MemAtN) => panic!("Not Implemented"),
 
@@ -963,8 +971,6 @@ impl Op {
                 let out = sub(registers, a, v);
 
                 registers.set8(ra, out);
-
-                4
             }
 
             Op::SUB(Destination8::Mem(r)) => {
@@ -972,13 +978,11 @@ impl Op {
                 let a = registers.get8(ra);
 
                 let m = registers.get16(r);
-                let v = mmu.get(m);
+                let v = bus.get(m);
 
                 let out = sub(registers, a, v);
 
                 registers.set8(ra, out);
-
-                8
             }
 
             Op::SUB(Destination8::N) => {
@@ -988,8 +992,6 @@ impl Op {
                 let out = sub(registers, a, args[0]);
 
                 registers.set8(ra, out);
-
-                8
             }
             Op::SUB(Destination8::MemAtN) => panic!("Not Implemented"),
 
@@ -1002,20 +1004,17 @@ impl Op {
                 let out = add(registers, a, v);
 
                 registers.set8(ra, out);
-                4
             }
 
             Op::ADD(Destination8::Mem(r)) => {
                 let a = registers.get8(&Registers8::A);
 
                 let m = registers.get16(r);
-                let v = mmu.get(m);
+                let v = bus.get(m);
 
                 let out = add(registers, a, v);
 
                 registers.set8(&Registers8::A, out);
-
-                8
             }
             Op::ADD(Destination8::N) => {
                 let ra = &Registers8::A;
@@ -1026,7 +1025,6 @@ impl Op {
                 let out = add(registers, a, v);
 
                 registers.set8(ra, out);
-                4
             }
             Op::ADD(Destination8::MemAtN) => panic!("Not Implemented"),
 
@@ -1039,19 +1037,17 @@ impl Op {
                 let out = adc(registers, a, v);
 
                 registers.set8(ra, out);
-                4
             }
             Op::ADC(Destination8::Mem(r)) => {
                 let ra = &Registers8::A;
                 let a = registers.get8(ra);
 
                 let mr = registers.get16(r);
-                let v = mmu.get(mr);
+                let v = bus.get(mr);
 
                 let out = adc(registers, a, v);
 
                 registers.set8(ra, out);
-                8
             }
             Op::ADC(Destination8::N) => {
                 let ra = &Registers8::A;
@@ -1062,42 +1058,44 @@ impl Op {
                 let out = adc(registers, a, v);
 
                 registers.set8(ra, out);
-                4
             }
             Op::ADC(Destination8::MemAtN) => panic!("Not Implemented"),
 
             Op::ADD16(ADD16Args::R(r1), ADD16Args::R(r2)) => {
                 let v = registers.get16(r2);
                 add16(registers, r1, v);
-                8
+                bus.idle();
             }
+            Op::ADD16(ADD16Args::R(r), ADD16Args::N) => {
+                let v = add_sp_signed(registers, args[0]);
+                registers.set16(r, v);
+                bus.idle();
+                bus.idle();
+            }
+            Op::ADD16(ADD16Args::N, _) => panic!("Not Implemented"),
 
             Op::RLCA => {
-                let v = registers.get8(&Registers8::A);
-                let out = rlc(registers, v);
-                registers.set8(&Registers8::A, out);
-                8
+                rotate_a(registers, rlc);
             }
-            Op::RRA => {
-                let v = registers.get8(&Registers8::A);
-                let out = rr(registers, v);
-                registers.set8(&Registers8::A, out);
-                8
+            Op::RLA => {
+                rotate_a(registers, rl);
             }
-            Op::DAA => {
-                /* When this instruction is executed, the A register is BCD corrected using the
-                 * contents of the flags. The exact process is the following: if the least
-                 * significant four bits of A contain a non-BCD digit (i. e. it is greater than 9)
-                 * or the H flag is set, then $06 is added to the register. Then the four most
-                 * significant bits are checked. If this more significant digit also happens to be
-                 * greater than 9 or the C flag is set, then $60 is added. */
-                4
+            Op::RRA => {
+                rotate_a(registers, rr);
             }
             Op::RRCA => {
-                let v = registers.get8(&Registers8::A);
-                let out = rrc(registers, v);
-                registers.set8(&Registers8::A, out);
-                8
+                rotate_a(registers, rrc);
+            }
+            /* When this instruction is executed, the A register is BCD corrected using the
+             * contents of the flags. The exact process is the following: if the least
+             * significant four bits of A contain a non-BCD digit (i. e. it is greater than 9)
+             * or the H flag is set, then $06 is added to the register. Then the four most
+             * significant bits are checked. If this more significant digit also happens to be
+             * greater than 9 or the C flag is set, then $60 is added. After a subtraction only
+             * the flags are looked at and the adjustment is subtracted instead.
+             */
+            Op::DAA => {
+                daa(registers);
             }
             Op::CPL => {
                 let r = &Registers8::A;
@@ -1106,17 +1104,18 @@ impl Op {
                 let out = cpl(registers, v);
 
                 registers.set8(r, out);
-                8
             }
 
             Op::CCF => {
                 let v = !registers.get_flag(Flag::C);
+                registers.set_flag(Flag::N, false);
+                registers.set_flag(Flag::H, false);
                 registers.set_flag(Flag::C, v);
-                4
             }
             Op::SCF => {
+                registers.set_flag(Flag::N, false);
+                registers.set_flag(Flag::H, false);
                 registers.set_flag(Flag::C, true);
-                4
             }
 
 
@@ -1125,15 +1124,13 @@ impl Op {
                 let v = registers.get8(r);
                 let out = rlc(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::RLC(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = rlc(registers, v);
 
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::RLC(Destination8::N) => panic!("Not Implemented"),
             Op::RLC(Destination8::MemAtN) => panic!("Not Implemented"),
@@ -1142,14 +1139,12 @@ impl Op {
                 let v = registers.get8(r);
                 let out = rrc(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::RRC(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = rrc(registers, v);
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::RRC(Destination8::N) => panic!("Not Implemented"),
             Op::RRC(Destination8::MemAtN) => panic!("Not Implemented"),
@@ -1158,24 +1153,26 @@ impl Op {
                 let v = registers.get8(r);
                 let out = rl(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::RL(Destination8::MemAtN) => panic!("Not Implemented"),
-            Op::RL(Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::RL(Destination8::Mem(r)) => {
+                let rm = registers.get16(r);
+                let v = bus.get(rm);
+                let out = rl(registers, v);
+                bus.set(rm, out);
+            }
             Op::RL(Destination8::N) => panic!("Not Implemented"),
 
             Op::RR(Destination8::R(r)) => {
                 let v = registers.get8(r);
                 let out = rr(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::RR(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = rr(registers, v);
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::RR(Destination8::MemAtN) => panic!("Not Implemented"),
             Op::RR(Destination8::N) => panic!("Not Implemented"),
@@ -1184,14 +1181,12 @@ impl Op {
                 let v = registers.get8(r);
                 let out = sla(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::SLA(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = sla(registers, v);
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::SLA(Destination8::MemAtN) => panic!("Not Implemented"),
             Op::SLA(Destination8::N) => panic!("Not Implemented"),
@@ -1200,14 +1195,12 @@ impl Op {
                 let v = registers.get8(r);
                 let out = sra(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::SRA(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = sra(registers, v);
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::SRA(Destination8::MemAtN) => panic!("Not Implemented"),
             Op::SRA(Destination8::N) => panic!("Not Implemented"),
@@ -1216,14 +1209,12 @@ impl Op {
                 let v = registers.get8(r);
                 let out = swap(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::SWAP(Destination8::Mem(r)) => {
                 let rm = registers.get16(r);
-                let v = mmu.get(rm);
+                let v = bus.get(rm);
                 let out = swap(registers, v);
-                mmu.set(rm, out);
-                16
+                bus.set(rm, out);
             }
             Op::SWAP(Destination8::MemAtN) => panic!("Not Implemented"),
             Op::SWAP(Destination8::N) => panic!("Not Implemented"),
@@ -1232,40 +1223,52 @@ impl Op {
                 let v = registers.get8(r);
                 let out = srl(registers, v);
                 registers.set8(r, out);
-                8
             }
             Op::SRL(Destination8::MemAtN) => panic!("Not Implemented"),
-            Op::SRL(Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::SRL(Destination8::Mem(r)) => {
+                let rm = registers.get16(r);
+                let v = bus.get(rm);
+                let out = srl(registers, v);
+                bus.set(rm, out);
+            }
             Op::SRL(Destination8::N) => panic!("Not Implemented"),
 
 
             Op::BIT(location, Destination8::R(r)) => {
                 let v = registers.get8(r);
                 bit(registers, v, *location);
-                8
             }
             Op::BIT(_, Destination8::MemAtN) => panic!("Not Implemented"),
-            Op::BIT(_, Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::BIT(location, Destination8::Mem(r)) => {
+                let v = bus.get(registers.get16(r));
+                bit(registers, v, *location);
+            }
             Op::BIT(_, Destination8::N) => panic!("Not Implemented"),
 
             Op::RES(location, Destination8::R(r)) => {
                 let v = registers.get8(r);
                 let out = res(v, *location);
                 registers.set8(r, out);
-                8
             }
             Op::RES(_, Destination8::MemAtN) => panic!("Not Implemented"),
-            Op::RES(_, Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::RES(location, Destination8::Mem(r)) => {
+                let rm = registers.get16(r);
+                let out = res(bus.get(rm), *location);
+                bus.set(rm, out);
+            }
             Op::RES(_, Destination8::N) => panic!("Not Implemented"),
 
             Op::SET(location, Destination8::R(r)) => {
                 let v = registers.get8(r);
                 let out = set(v, *location);
                 registers.set8(r, out);
-                8
             }
             Op::SET(_, Destination8::MemAtN) => panic!("Not Implemented"),
-            Op::SET(_, Destination8::Mem(_)) => panic!("Not Implemented"),
+            Op::SET(location, Destination8::Mem(r)) => {
+                let rm = registers.get16(r);
+                let out = set(bus.get(rm), *location);
+                bus.set(rm, out);
+            }
             Op::SET(_, Destination8::N) => panic!("Not Implemented"),
         }
     }
@@ -1315,7 +1318,7 @@ pub fn new() -> Instructions {
     instructions[0x0014] = Op::INC8(Destination8::R(Registers8::D));
     instructions[0x0015] = Op::DEC8(Destination8::R(Registers8::D));
     instructions[0x0016] = Op::LD8(Destination8::R(Registers8::D), Destination8::N);
-    instructions[0x0017] = Op::RL(Destination8::R(Registers8::A));
+    instructions[0x0017] = Op::RLA;
     instructions[0x0018] = Op::JR(JrArgs::N);
     instructions[0x0019] = Op::ADD16(ADD16Args::R(Registers16::HL), ADD16Args::R(Registers16::DE));
     instructions[0x001A] = Op::LD8(Destination8::R(Registers8::A), Destination8::Mem(Registers16::DE));
@@ -1332,7 +1335,7 @@ pub fn new() -> Instructions {
     instructions[0x0024] = Op::INC8(Destination8::R(Registers8::H));
     instructions[0x0025] = Op::DEC8(Destination8::R(Registers8::H));
     instructions[0x0026] = Op::LD8(Destination8::R(Registers8::H), Destination8::N);
-    instructions[0x0027] = Op::NotImplemented;
+    instructions[0x0027] = Op::DAA;
     instructions[0x0028] = Op::JR(JrArgs::CheckFlag(CheckFlag::Z));
     instructions[0x0029] = Op::ADD16(ADD16Args::R(Registers16::HL), ADD16Args::R(Registers16::HL));
     instructions[0x002A] = Op::LoadAndIncR;
@@ -1515,35 +1518,35 @@ pub fn new() -> Instructions {
     instructions[0x00D0] = Op::RET(Some(CheckFlag::NC));
     instructions[0x00D1] = Op::POP(Registers16::DE);
     instructions[0x00D2] = Op::JP(JpArgs::CheckFlag(CheckFlag::NC));
-    instructions[0x00D3] = Op::NotImplemented;
-    instructions[0x00D4] = Op::CALL(Some(CheckFlag::Z));
+    instructions[0x00D3] = Op::Illegal;
+    instructions[0x00D4] = Op::CALL(Some(CheckFlag::NC));
     instructions[0x00D5] = Op::PUSH(Registers16::DE);
     instructions[0x00D6] = Op::SUB(Destination8::N);
     instructions[0x00D7] = Op::RST(RstArgs::H10);
     instructions[0x00D8] = Op::RET(Some(CheckFlag::C));
     instructions[0x00D9] = Op::RETI;
     instructions[0x00DA] = Op::JP(JpArgs::CheckFlag(CheckFlag::C));
-    instructions[0x00DB] = Op::NotImplemented;
+    instructions[0x00DB] = Op::Illegal;
     instructions[0x00DC] = Op::CALL(Some(CheckFlag::C));
-    instructions[0x00DD] = Op::NotImplemented;
+    instructions[0x00DD] = Op::Illegal;
     instructions[0x00DE] = Op::SBC(Destination8::N);
     instructions[0x00DF] = Op::RST(RstArgs::H18);
 
     instructions[0x00E0] = Op::LoadFF00(LoadFF00Targets::N, LoadFF00Targets::A);
     instructions[0x00E1] = Op::POP(Registers16::HL);
     instructions[0x00E2] = Op::LoadFF00(LoadFF00Targets::C, LoadFF00Targets::A);
-    instructions[0x00E3] = Op::NotImplemented;
-    instructions[0x00E4] = Op::NotImplemented;
+    instructions[0x00E3] = Op::Illegal;
+    instructions[0x00E4] = Op::Illegal;
     instructions[0x00E5] = Op::PUSH(Registers16::HL);
     instructions[0x00E6] = Op::AND(Destination8::N);
     instructions[0x00E7] = Op::RST(RstArgs::H20);
     // Note that this instruction actually takes a signed 8bit value
-    // instructions[0x00E8] = Op::ADD16(ADD16Args::R(Registers16::SP), ADD16Args::N);
+    instructions[0x00E8] = Op::ADD16(ADD16Args::R(Registers16::SP), ADD16Args::N);
     instructions[0x00E9] = Op::JP(JpArgs::HL);
     instructions[0x00EA] = Op::LD8(Destination8::N, Destination8::R(Registers8::A));
-    instructions[0x00EB] = Op::NotImplemented;
-    instructions[0x00EC] = Op::NotImplemented;
-    instructions[0x00ED] = Op::NotImplemented;
+    instructions[0x00EB] = Op::Illegal;
+    instructions[0x00EC] = Op::Illegal;
+    instructions[0x00ED] = Op::Illegal;
     instructions[0x00EE] = Op::XOR(Destination8::N);
     instructions[0x00EF] = Op::RST(RstArgs::H28);
 
@@ -1551,16 +1554,16 @@ pub fn new() -> Instructions {
     instructions[0x00F1] = Op::POP(Registers16::AF);
     instructions[0x00F2] = Op::LoadFF00(LoadFF00Targets::A, LoadFF00Targets::C);
     instructions[0x00F3] = Op::DI;
-    instructions[0x00F4] = Op::NotImplemented;
+    instructions[0x00F4] = Op::Illegal;
     instructions[0x00F5] = Op::PUSH(Registers16::AF);
     instructions[0x00F6] = Op::OR(Destination8::N);
     instructions[0x00F7] = Op::RST(RstArgs::H30);
-    // instructions[0x00F8] = LD HL,SP+r8
+    instructions[0x00F8] = Op::LoadHLSP;
     instructions[0x00F9] = Op::LD16(Destination16::R(Registers16::SP), Destination16::R(Registers16::HL));
     instructions[0x00FA] = Op::LD8(Destination8::R(Registers8::A), Destination8::MemAtN);
     instructions[0x00FB] = Op::EI;
-    instructions[0x00FC] = Op::NotImplemented;
-    instructions[0x00FD] = Op::NotImplemented;
+    instructions[0x00FC] = Op::Illegal;
+    instructions[0x00FD] = Op::Illegal;
     instructions[0x00FE] = Op::CP(Destination8::N);
     instructions[0x00FF] = Op::RST(RstArgs::H38);
 
@@ -1847,19 +1850,45 @@ pub fn new() -> Instructions {
 mod tests {
     use super::*;
     use cpu;
+    use framebuffer;
+    use gpu;
+    use mmu;
+    use palette;
     use registers;
     use device::boot_rom;
     use device::cartridge;
+    use device::joypad::Button;
     use config;
 
+    /* Everything a bus borrows, with `program` in the boot rom */
+    struct Hardware {
+        mmu: mmu::MMU,
+        gpu: gpu::GPU,
+        framebuffer: framebuffer::Framebuffer,
+    }
+
+    impl Hardware {
+        fn bus(&mut self) -> bus::Bus<'_> {
+            bus::new(&mut self.mmu, &mut self.gpu, &mut self.framebuffer)
+        }
+    }
+
+    fn hardware(program: &[u8]) -> Hardware {
+        Hardware {
+            mmu: mmu::new(boot_rom::new(program), cartridge::zero()),
+            gpu: gpu::new(),
+            framebuffer: [palette::Shade::White; 23040],
+        }
+    }
+
     #[test]
     fn test_reading_gbm() {
         let instructions = new();
         let mut registers = registers::new();
-        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+        let mut hw = hardware(&[]);
         let mut cpu = cpu::new(config::zero());
 
-        assert_eq!(4, cpu.tick(&instructions, &mut registers, &mut mmu));
+        assert_eq!(4, cpu.tick(&instructions, &mut registers, &mut hw.bus()));
         assert_eq!(1, registers.get16(&Registers16::PC));
         assert_eq!(0xFFFE, registers.get16(&Registers16::SP));
     }
@@ -1869,13 +1898,109 @@ mod tests {
         let instructions = new();
         let mut context = cpu::Context::new();
         let mut registers = registers::new();
-        let mut mmu = mmu::new(boot_rom::zero(), cartridge::zero());
+        let mut hw = hardware(&[]);
         let mut cpu = cpu::new(config::zero());
 
-        assert_eq!(cpu.execute(&mut context, &Op::DI, &mut registers, &mut mmu), 4);
+        cpu.execute(&mut context, &Op::DI, &mut registers, &mut hw.bus());
         assert_eq!(registers.get_interrupts_enabled(), false);
     }
 
+    #[test]
+    fn test_ei_delay_and_interrupt_dispatch() {
+        let instructions = new();
+        let mut registers = registers::new();
+        // EI, NOP, NOP
+        let mut hw = hardware(&[0xFB, 0x00, 0x00]);
+        let mut cpu = cpu::new(config::zero());
+
+        hw.mmu.set(0xFFFF, 0x05);
+        hw.mmu.set(0xFF0F, 0x05);
+        assert_eq!(hw.mmu.get(0xFF0F), 0xE5);
+
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get_interrupts_enabled(), false);
+
+        // the instruction after EI still runs before the interrupt
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get_interrupts_enabled(), true);
+        assert_eq!(registers.get16(&Registers16::PC), 2);
+
+        // only VBlank, the highest priority, is dispatched
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 20);
+        assert_eq!(registers.get16(&Registers16::PC), 0x40);
+        assert_eq!(registers.get16(&Registers16::SP), 0xFFFC);
+        assert_eq!(hw.mmu.get(0xFFFC), 0x02);
+        assert_eq!(hw.mmu.get(0xFFFD), 0x00);
+        assert_eq!(hw.mmu.get(0xFF0F), 0xE4);
+        assert_eq!(registers.get_interrupts_enabled(), false);
+    }
+
+    #[test]
+    fn test_halt_wakes_on_pending_interrupt_without_ime() {
+        let instructions = new();
+        let mut context = cpu::Context::new();
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+        let mut cpu = cpu::new(config::zero());
+
+        hw.mmu.set(0xFFFF, 0x04);
+        cpu.execute(&mut context, &Op::HALT, &mut registers, &mut hw.bus());
+
+        // time keeps passing while halted
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 4);
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 4);
+        assert_eq!(registers.get16(&Registers16::PC), 0);
+
+        hw.mmu.hardware_io.interrupts.set(0x04);
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get16(&Registers16::PC), 1);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        let instructions = new();
+        let mut context = cpu::Context::new();
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+        let mut cpu = cpu::new(config::zero());
+
+        hw.mmu.set(0xFFFF, 0x04);
+        hw.mmu.hardware_io.interrupts.set(0x04);
+        cpu.execute(&mut context, &Op::HALT, &mut registers, &mut hw.bus());
+
+        // the byte after HALT runs twice
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get16(&Registers16::PC), 0);
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get16(&Registers16::PC), 1);
+    }
+
+    #[test]
+    fn test_stop() {
+        let instructions = new();
+        let mut context = cpu::Context::new();
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+        let mut cpu = cpu::new(config::zero());
+
+        hw.mmu.tick(1024);
+        assert_eq!(hw.mmu.get(0xFF04), 4);
+
+        cpu.execute(&mut context, &Op::STOP, &mut registers, &mut hw.bus());
+        assert_eq!(registers.get16(&Registers16::PC), 1);
+        assert_eq!(hw.mmu.get(0xFF04), 0);
+        assert!(cpu.stopped());
+
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert!(cpu.stopped());
+
+        hw.mmu.set(0xFF00, 0x10);
+        hw.mmu.hardware_io.joypad.set_button(Button::Start, true, &mut hw.mmu.hardware_io.interrupts);
+        cpu.tick(&instructions, &mut registers, &mut hw.bus());
+        assert!(!cpu.stopped());
+    }
+
     #[test]
     fn test_swap() {
         // This is 11110000 in binary
@@ -2023,4 +2148,237 @@ mod tests {
 
         assert_eq!(res(n, 0), 0xFE);
     }
+
+    #[test]
+    fn test_rlc_rrc_rotate() {
+        let mut registers = registers::new();
+
+        assert_eq!(rlc(&mut registers, 0x85), 0x0B);
+        assert_eq!(registers.get_flag(Flag::C), true);
+
+        assert_eq!(rrc(&mut registers, 0x01), 0x80);
+        assert_eq!(registers.get_flag(Flag::C), true);
+    }
+
+    #[test]
+    fn test_rotate_a_clears_z() {
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+
+        registers.set8(&Registers8::A, 0x80);
+        Op::RLA.call(&mut registers, &mut hw.bus(), &vec![]);
+        assert_eq!(registers.get8(&Registers8::A), 0x00);
+        assert_eq!(registers.get_flag(Flag::Z), false);
+        assert_eq!(registers.get_flag(Flag::C), true);
+    }
+
+    #[test]
+    fn test_adc_sbc_carry_in() {
+        let mut registers = registers::new();
+
+        registers.set_flag(Flag::C, true);
+        assert_eq!(adc(&mut registers, 0x0F, 0xF0), 0x00);
+        assert_eq!(registers.get_flag(Flag::Z), true);
+        assert_eq!(registers.get_flag(Flag::H), true);
+        assert_eq!(registers.get_flag(Flag::C), true);
+
+        registers.set_flag(Flag::C, true);
+        assert_eq!(sbc(&mut registers, 0x10, 0x0F), 0x00);
+        assert_eq!(registers.get_flag(Flag::H), true);
+        assert_eq!(registers.get_flag(Flag::C), false);
+
+        registers.set_flag(Flag::C, true);
+        assert_eq!(sbc(&mut registers, 0x00, 0xFF), 0x00);
+        assert_eq!(registers.get_flag(Flag::C), true);
+    }
+
+    #[test]
+    fn test_daa() {
+        let mut registers = registers::new();
+
+        // 0x19 + 0x28 = 0x41, 47 in bcd
+        let out = add(&mut registers, 0x19, 0x28);
+        registers.set8(&Registers8::A, out);
+        daa(&mut registers);
+        assert_eq!(registers.get8(&Registers8::A), 0x47);
+        assert_eq!(registers.get_flag(Flag::C), false);
+
+        // 0x99 + 0x01 carries out to 100
+        let out = add(&mut registers, 0x99, 0x01);
+        registers.set8(&Registers8::A, out);
+        daa(&mut registers);
+        assert_eq!(registers.get8(&Registers8::A), 0x00);
+        assert_eq!(registers.get_flag(Flag::Z), true);
+        assert_eq!(registers.get_flag(Flag::C), true);
+
+        // 0x42 - 0x15 = 0x2D, 27 in bcd
+        let out = sub(&mut registers, 0x42, 0x15);
+        registers.set8(&Registers8::A, out);
+        daa(&mut registers);
+        assert_eq!(registers.get8(&Registers8::A), 0x27);
+        assert_eq!(registers.get_flag(Flag::N), true);
+    }
+
+    #[test]
+    fn test_sp_plus_signed() {
+        let instructions = new();
+        let mut registers = registers::new();
+        // LD HL,SP+1; ADD SP,-2
+        let mut hw = hardware(&[0xF8, 0x01, 0xE8, 0xFE]);
+        let mut cpu = cpu::new(config::zero());
+
+        registers.set16(&Registers16::SP, 0x00FF);
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 12);
+        assert_eq!(registers.get16(&Registers16::HL), 0x0100);
+        assert_eq!(registers.get_flag(Flag::H), true);
+        assert_eq!(registers.get_flag(Flag::C), true);
+
+        // the flags come from the low byte even when going down
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 16);
+        assert_eq!(registers.get16(&Registers16::SP), 0x00FD);
+        assert_eq!(registers.get_flag(Flag::Z), false);
+        assert_eq!(registers.get_flag(Flag::H), true);
+        assert_eq!(registers.get_flag(Flag::C), true);
+    }
+
+    #[test]
+    fn test_ld_nn_sp_is_little_endian() {
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+
+        registers.set16(&Registers16::SP, 0x1234);
+        instructions_op(0x08).call(&mut registers, &mut hw.bus(), &vec![0x00, 0xC0]);
+        assert_eq!(hw.mmu.get(0xC000), 0x34);
+        assert_eq!(hw.mmu.get(0xC001), 0x12);
+    }
+
+    #[test]
+    fn test_pop_af_masks_flags() {
+        let mut registers = registers::new();
+        let mut hw = hardware(&[]);
+
+        registers.set16(&Registers16::SP, 0xC000);
+        hw.mmu.set(0xC000, 0xFF);
+        hw.mmu.set(0xC001, 0x12);
+        Op::POP(Registers16::AF).call(&mut registers, &mut hw.bus(), &vec![]);
+        assert_eq!(registers.get16(&Registers16::AF), 0x12F0);
+    }
+
+    #[test]
+    fn test_every_opcode_is_in_the_table() {
+        let instructions = new();
+
+        for opcode in 0..=255 {
+            match instructions.get(opcode) {
+                Op::NotImplemented => panic!("{:02X} missing", opcode),
+                _ => {}
+            }
+            match instructions.get_cb(opcode) {
+                Op::NotImplemented => panic!("CB {:02X} missing", opcode),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_illegal_opcode_locks_up() {
+        let instructions = new();
+        let mut registers = registers::new();
+        let mut hw = hardware(&[0xD3, 0x00]);
+        let mut cpu = cpu::new(config::zero());
+
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 4);
+        assert!(cpu.locked());
+
+        // time keeps passing but even an enabled interrupt doesn't get it going again
+        hw.mmu.set(0xFFFF, 0x01);
+        hw.mmu.set(0xFF0F, 0x01);
+        registers.set_interrupts_enabled(true);
+        for _ in 0..10 {
+            assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 4);
+        }
+        assert!(cpu.locked());
+        assert_eq!(registers.get16(&Registers16::PC), 1);
+    }
+
+    #[test]
+    fn test_instruction_cycles() {
+        let instructions = new();
+
+        // flags are all clear so NZ and NC are taken, Z and C aren't
+        let programs: Vec<(&[u8], u8)> = vec![
+            (&[0x00], 4),
+            (&[0x01, 0x00, 0xC0], 12),
+            (&[0x03], 8),
+            (&[0x06, 0x12], 8),
+            (&[0x08, 0x00, 0xC0], 20),
+            (&[0x09], 8),
+            (&[0x18, 0x00], 12),
+            (&[0x20, 0x00], 12),
+            (&[0x28, 0x00], 8),
+            (&[0x34], 12),
+            (&[0x36, 0x12], 12),
+            (&[0x7E], 8),
+            (&[0x86], 8),
+            (&[0xC0], 20),
+            (&[0xC1], 12),
+            (&[0xC2, 0x00, 0x00], 16),
+            (&[0xC3, 0x00, 0x00], 16),
+            (&[0xC4, 0x00, 0x00], 24),
+            (&[0xC5], 16),
+            (&[0xC6, 0x12], 8),
+            (&[0xC7], 16),
+            (&[0xC8], 8),
+            (&[0xC9], 16),
+            (&[0xCA, 0x00, 0x00], 12),
+            (&[0xCB, 0x00], 8),
+            (&[0xCB, 0x06], 16),
+            (&[0xCB, 0x46], 12),
+            (&[0xCC, 0x00, 0x00], 12),
+            (&[0xCD, 0x00, 0x00], 24),
+            (&[0xD9], 16),
+            (&[0xE0, 0x80], 12),
+            (&[0xE2], 8),
+            (&[0xE8, 0x01], 16),
+            (&[0xE9], 4),
+            (&[0xEA, 0x00, 0xC0], 16),
+            (&[0xF0, 0x80], 12),
+            (&[0xF8, 0x01], 12),
+            (&[0xF9], 8),
+            (&[0xFA, 0x00, 0xC0], 16),
+        ];
+
+        for (program, cycles) in programs {
+            let mut registers = registers::new();
+            let mut hw = hardware(program);
+            let mut cpu = cpu::new(config::zero());
+
+            let mut taken = cpu.tick(&instructions, &mut registers, &mut hw.bus());
+            if program[0] == 0xCB {
+                taken += cpu.tick(&instructions, &mut registers, &mut hw.bus());
+            }
+            assert_eq!(taken, cycles, "{:02X?}", program);
+        }
+    }
+
+    #[test]
+    fn test_reads_happen_part_way_through_an_instruction() {
+        let instructions = new();
+        let mut registers = registers::new();
+        // LD A,(0xFF05)
+        let mut hw = hardware(&[0xFA, 0x05, 0xFF]);
+        let mut cpu = cpu::new(config::zero());
+
+        // TIMA counts every 16 cycles and is 8 cycles from its next increment
+        hw.mmu.set(0xFF07, 0x05);
+        hw.mmu.tick(8);
+
+        // the read is the instruction's fourth M-cycle, after TIMA has gone up
+        assert_eq!(cpu.tick(&instructions, &mut registers, &mut hw.bus()), 16);
+        assert_eq!(registers.get8(&Registers8::A), 1);
+    }
+
+    fn instructions_op(opcode: u8) -> Op {
+        *new().get(opcode)
+    }
 }
@@ -2,13 +2,16 @@ extern crate sdl2;
 #[macro_use]
 extern crate clap;
 
+mod bus;
 mod bytes;
+mod capture;
 mod cpu;
 mod device;
 mod tile;
 mod display;
 mod framebuffer;
 mod gameboy;
+mod gbs;
 mod gpu;
 mod instructions;
 mod mmu;
@@ -16,14 +19,84 @@ mod palette;
 mod registers;
 mod config;
 mod repl;
+mod resampler;
+mod save_file;
+mod wav;
+
+/* Renders the gameboy's audio to the WAV file at `path` as the WAV_FRAMES and WAV_STEMS options ask */
+fn write_wav(matches: &clap::ArgMatches, gameboy: &mut gameboy::Gameboy, path: &str) {
+    let frames = match matches.value_of("WAV_FRAMES").unwrap_or("600").parse() {
+        Ok(frames) => frames,
+        Err(_) => {
+            eprintln!("--wav_frames takes a number of frames");
+            std::process::exit(1);
+        }
+    };
+
+    let recording = capture::record(gameboy, frames, 48000, matches.is_present("WAV_STEMS"));
+    if let Err(e) = recording.write(std::path::Path::new(path)) {
+        eprintln!("Could not write {}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+fn play_gbs(matches: &clap::ArgMatches) {
+    let path = matches.value_of("GBS_FILE").unwrap();
+
+    let gbs = match gbs::load(path) {
+        Ok(gbs) => gbs,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    print!("{}", gbs.header);
+
+    let song = match matches.value_of("SONG").map(|s| s.parse::<u8>()) {
+        Some(Ok(n)) if n >= 1 => n - 1,
+        Some(_) => {
+            eprintln!("--song takes a song number starting from 1");
+            std::process::exit(1);
+        }
+        None => gbs.header.first_song.max(1) - 1,
+    };
+
+    let config = config::gbs(path, config::debug_default());
+
+    if let Some(wav_path) = matches.value_of("WAV") {
+        let mut gameboy = match gbs.gameboy(&config, song) {
+            Ok(gameboy) => gameboy,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+
+        write_wav(matches, &mut gameboy, wav_path);
+    } else {
+        let mut display = display::new(&config);
+
+        display.play_gbs(&gbs, song);
+    }
+}
 
 fn main() {
     let matches = clap_app!(anders_gameboy_emulator =>
         (version: "0.1")
         (author: "Anders Conbere <anders@conbere.org>")
         (about: "Emulates a gameboy")
+        (@setting SubcommandsNegateReqs)
         (@arg BOOT_ROM: --boot_rom +takes_value +required "The file of the boot rom to load")
         (@arg GAME_ROM: --game_rom +takes_value +required "The file of the game rom to load")
+        (@arg SAVE_FILE: --save_file +takes_value "Where to keep battery backed cartridge ram, defaults to the game rom with a .sav extension")
+        (@arg RTC_WALL_CLOCK: --rtc_wall_clock "Advance the cartridge clock by the time passed since the save file was written")
+        (@arg SERIAL_LOG: --serial_log +takes_value conflicts_with[LINK_LISTEN LINK_CONNECT] "Write every byte sent over the serial port to this file on exit instead of printing it")
+        (@arg LINK_LISTEN: --link_listen +takes_value conflicts_with[LINK_CONNECT] "Wait for another emulator to connect a link cable on host:port or unix:<path>")
+        (@arg LINK_CONNECT: --link_connect +takes_value "Connect a link cable to another emulator listening on host:port or unix:<path>")
+        (@arg WAV: --wav +takes_value "Render the game's audio to this WAV file instead of opening a window")
+        (@arg WAV_FRAMES: --wav_frames +takes_value requires[WAV] "How many frames of audio to render, defaults to 600 (about 10 seconds)")
+        (@arg WAV_STEMS: --wav_stems requires[WAV] "Also write each sound channel to its own WAV file next to the mix")
         (@subcommand debug =>
             (@arg FRAME_COUNT: --frame_count "Print frame count to display.")
             (@arg LOG_INSTRUCTIONS: --log_instructions "Print each instruction to stdout.")
@@ -32,8 +105,27 @@ fn main() {
             (@arg BREAK_POINT_PC: --break_point_pc +takes_value "Frame to pause instruction at.")
             (@arg REPL: --repl "Boots the emulator into debug mode.")
         )
+        (@subcommand gbs =>
+            (about: "Plays a GBS music file, left and right change song")
+            (@arg GBS_FILE: +required "The GBS file to play")
+            (@arg SONG: --song +takes_value "The song to start on, defaults to the file's first")
+            (@arg WAV: --wav +takes_value "Render the song to this WAV file instead of playing it")
+            (@arg WAV_FRAMES: --wav_frames +takes_value requires[WAV] "How many frames of audio to render, defaults to 600 (about 10 seconds)")
+            (@arg WAV_STEMS: --wav_stems requires[WAV] "Also write each sound channel to its own WAV file next to the mix")
+        )
     ).get_matches();
 
+    if let Some(gbs_matches) = matches.subcommand_matches("gbs") {
+        play_gbs(gbs_matches);
+        return;
+    }
+
+    /* the gbs subcommand doesn't need roms so clap can't require them */
+    if !matches.is_present("BOOT_ROM") || !matches.is_present("GAME_ROM") {
+        eprintln!("{}\n\n--boot_rom and --game_rom are required", matches.usage());
+        std::process::exit(1);
+    }
+
     let debug = match matches.subcommand_matches("debug") {
         Some(debug_matches) => config::new_debug(
             debug_matches.is_present("FRAME_COUNT"),
@@ -50,11 +142,59 @@ fn main() {
     let config = config::new(
         matches.value_of("BOOT_ROM").unwrap(),
         matches.value_of("GAME_ROM").unwrap(),
+        matches.value_of("SAVE_FILE"),
+        matches.is_present("RTC_WALL_CLOCK"),
         debug,
     ).unwrap();
 
-    let mut gameboy = gameboy::new(&config);
-    let mut display = display::new(&config);
+    let mut gameboy = match gameboy::new(&config) {
+        Ok(gameboy) => gameboy,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    print!("{}", gameboy.cartridge_header());
+
+    /* test roms report their results over the serial port, print it unless it's being logged */
+    let serial_log = matches.value_of("SERIAL_LOG").map(|path| {
+        let capture = device::serial::capture();
+        let buffer = capture.buffer();
+        gameboy.set_link(Box::new(capture));
+        (path, buffer)
+    });
+
+    let link = matches
+        .value_of("LINK_LISTEN")
+        .map(device::socket_link::listen)
+        .or_else(|| matches.value_of("LINK_CONNECT").map(device::socket_link::connect));
+
+    match link {
+        Some(Ok(link)) => gameboy.set_link(Box::new(link)),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        None if serial_log.is_none() => gameboy.set_link(Box::new(device::serial::Printer)),
+        None => {}
+    }
+
+    if let Some(path) = matches.value_of("WAV") {
+        write_wav(&matches, &mut gameboy, path);
+    } else {
+        let mut display = display::new(&config);
+
+        display.start(&mut gameboy);
+    }
+
+    if let Err(e) = gameboy.save() {
+        eprintln!("Could not write save file {}: {}", config.save_file, e);
+    }
 
-    display.start(&mut gameboy);
+    if let Some((path, buffer)) = serial_log {
+        if let Err(e) = std::fs::write(path, &*buffer.borrow()) {
+            eprintln!("Could not write serial log {}: {}", path, e);
+        }
+    }
 }
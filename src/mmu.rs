@@ -2,16 +2,24 @@
  * http://gameboy.mongenel.com/dmg/asmmemmap.html
  */
 
-use bytes;
 use device;
 use device::Device;
 use device::boot_rom;
 use device::cartridge;
+use device::object_attribute_memory;
 use device::tile_map;
 use device::tile_data;
 
+/* An OAM DMA transfer in progress, it copies a byte a M-cycle after a one M-cycle delay */
+struct Dma {
+    source: u16,
+    index: u16,
+    delay: u32,
+}
+
 pub struct MMU {
     boot_rom_loaded: bool,
+    dma: Option<Dma>,
 
     pub boot_rom: boot_rom::BootRom,
     pub cartridge: cartridge::Cartridge,
@@ -19,11 +27,10 @@ pub struct MMU {
     pub tile_map_2: tile_map::TileMap,
     pub tile_data_1: tile_data::TileData,
     pub tile_data_2: tile_data::TileData,
-    pub cartridge_ram: device::not_implemented::NotImplemented,
     pub internal_ram_bank_0: device::ram_bank::RamBank,
     pub internal_ram_bank_1: device::ram_bank::RamBank,
     pub echo_ram: device::not_implemented::NotImplemented,
-    pub object_attribute_memory: device::not_implemented::NotImplemented,
+    pub object_attribute_memory: object_attribute_memory::ObjectAttributeMemory,
     pub unusable_memory: device::not_implemented::NotImplemented,
     pub hardware_io: device::hardware_io::HardwareIO,
     pub zero_page: device::zero_page::ZeroPage,
@@ -31,13 +38,67 @@ pub struct MMU {
 }
 
 impl MMU {
-    pub fn get16(&self, address: u16) -> u16 {
-        let mh = self.get(address);
-        let ml = self.get(address + 1);
-        bytes::combine_little(mh, ml)
+    /* While an OAM DMA is copying the cpu is cut off from the rest of the bus, only HRAM at
+     * 0xFF80-0xFFFE can be reached. Reads from anywhere else see 0xFF and writes are dropped.
+     */
+    fn dma_blocked(&self, address: u16) -> bool {
+        match self.dma {
+            Some(ref dma) => dma.delay == 0 && !(0xFF80..=0xFFFE).contains(&address),
+            None => false,
+        }
+    }
+
+    /* Writing 0xFF46 copies 160 bytes from 0xXX00 (XX being the value written) into OAM */
+    fn start_dma(&mut self, v: u8) {
+        self.dma = Some(Dma {
+            source: (v as u16) << 8,
+            index: 0,
+            delay: 4,
+        });
+    }
+
+    /* Runs the memory side of the hardware forward by `cycles`, that's the cartridge, the io
+     * registers and any OAM DMA in progress.
+     */
+    pub fn tick(&mut self, cycles: u32) {
+        self.cartridge.tick(cycles);
+        self.hardware_io.tick(cycles);
+
+        for _ in 0..cycles / 4 {
+            let (source, index) = match self.dma {
+                Some(ref mut dma) if dma.delay > 0 => {
+                    dma.delay -= 4;
+                    continue;
+                }
+                Some(ref mut dma) => {
+                    dma.index += 1;
+                    (dma.source, dma.index - 1)
+                }
+                None => break,
+            };
+
+            /* sources past 0xDFFF read the echo of work ram */
+            let address = source + index;
+            let address = if address >= 0xE000 { address - 0x2000 } else { address };
+
+            let v = self.read(address);
+            self.object_attribute_memory.set(index, v);
+
+            if index == 159 {
+                self.dma = None;
+            }
+        }
     }
 
     pub fn get(&self, address: u16) -> u8 {
+        if self.dma_blocked(address) {
+            return 0xFF;
+        }
+
+        self.read(address)
+    }
+
+    fn read(&self, address: u16) -> u8 {
         let k = device::get_kind(address);
 
         match k {
@@ -57,11 +118,11 @@ impl MMU {
             device::Kind::TileMap1 => self.tile_map_1.get(address - 0x9800),
             device::Kind::TileMap2 => self.tile_map_2.get(address - 0x9C00),
 
-            device::Kind::CartridgeRAM => self.cartridge_ram.get(address),
+            device::Kind::CartridgeRAM => self.cartridge.get_ram(address - 0xA000),
             device::Kind::InternalRAMBank0 => self.internal_ram_bank_0.get(address - 0xC000),
             device::Kind::InternalRAMBank1 => self.internal_ram_bank_1.get(address - 0xD000),
             device::Kind::EchoRAM => self.echo_ram.get(address),
-            device::Kind::ObjectAttributeMemory => self.object_attribute_memory.get(address),
+            device::Kind::ObjectAttributeMemory => self.object_attribute_memory.get(address - 0xFE00),
             device::Kind::UnusableMemory => self.unusable_memory.get(address),
             device::Kind::HardwareIORegisters => self.hardware_io.get(address - 0xFF00),
             device::Kind::ZeroPage => self.zero_page.get(address - 0xFF80),
@@ -70,32 +131,37 @@ impl MMU {
     }
 
     pub fn set(&mut self, address: u16, v: u8) {
+        if self.dma_blocked(address) {
+            return;
+        }
+
         let k = device::get_kind(address);
 
         match k {
-            device::Kind::RestartAndInterrupt => {
-                if self.boot_rom_loaded {
-                    println!("can't write to boot rom address: {:X} value: {:X}", address, v)
-                } else {
-                    println!("can't write to cartridge rom address: {:X} value: {:X}", address, v)
-                }
-            },
-            device::Kind::CartridgeHeader
+            /* The rom is read only, writes to it land in the memory bank controller's registers
+             * whether or not the boot rom is mapped over the start of it.
+             */
+            device::Kind::RestartAndInterrupt
+            | device::Kind::CartridgeHeader
             | device::Kind::CartridgeROMBank0
-            | device::Kind::CartridgeROMBank1 => panic!("can't write to cartridge header address: {:X}, value: {:X}", address, v),
+            | device::Kind::CartridgeROMBank1 => self.cartridge.set(address, v),
 
             device::Kind::TileData1 => self.tile_data_1.set(address - 0x8000, v),
             device::Kind::TileData2 => self.tile_data_2.set(address - 0x8800, v),
             device::Kind::TileMap1 => self.tile_map_1.set(address - 0x9800, v),
             device::Kind::TileMap2 => self.tile_map_2.set(address - 0x9C00, v),
 
-            device::Kind::CartridgeRAM => self.cartridge_ram.set(address, v),
+            device::Kind::CartridgeRAM => self.cartridge.set_ram(address - 0xA000, v),
             device::Kind::InternalRAMBank0 => self.internal_ram_bank_0.set(address - 0xC000, v),
             device::Kind::InternalRAMBank1 => self.internal_ram_bank_1.set(address - 0xD000, v),
             device::Kind::EchoRAM => self.echo_ram.set(address, v),
-            device::Kind::ObjectAttributeMemory => self.object_attribute_memory.set(address, v),
+            device::Kind::ObjectAttributeMemory => self.object_attribute_memory.set(address - 0xFE00, v),
             device::Kind::UnusableMemory => self.unusable_memory.set(address, v),
             device::Kind::HardwareIORegisters => match address {
+                0xFF46 => {
+                    self.hardware_io.set(address - 0xFF00, v);
+                    self.start_dma(v);
+                }
                 0xFF50 => self.boot_rom_loaded = false,
                 _ => self.hardware_io.set(address - 0xFF00, v),
             },
@@ -108,20 +174,54 @@ impl MMU {
 pub fn new(boot_rom: boot_rom::BootRom, cartridge: cartridge::Cartridge) -> MMU {
     MMU {
         boot_rom_loaded: true,
+        dma: None,
         boot_rom: boot_rom,
         cartridge: cartridge,
         tile_map_1: tile_map::new(),
         tile_map_2: tile_map::new(),
         tile_data_1: tile_data::new(tile_data::TileDataKind::Bottom),
         tile_data_2: tile_data::new(tile_data::TileDataKind::Top),
-        cartridge_ram: device::not_implemented::NotImplemented {},
         internal_ram_bank_0: device::ram_bank::new(),
         internal_ram_bank_1: device::ram_bank::new(),
         echo_ram: device::not_implemented::NotImplemented {},
-        object_attribute_memory: device::not_implemented::NotImplemented {},
+        object_attribute_memory: object_attribute_memory::new(),
         unusable_memory: device::not_implemented::NotImplemented {},
         hardware_io: device::hardware_io::new(),
         zero_page: device::zero_page::new(),
         interrupt_enable: device::interrupt::new_enabled(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oam_dma() {
+        let mut mmu = new(boot_rom::zero(), cartridge::zero());
+
+        for i in 0..160 {
+            mmu.set(0xC100 + i, i as u8);
+        }
+        mmu.set(0xFF80, 0x12);
+
+        mmu.set(0xFF46, 0xC1);
+        assert_eq!(mmu.get(0xFF46), 0xC1);
+
+        // the startup delay and then half the transfer
+        mmu.tick(4 + 80 * 4);
+        assert_eq!(mmu.object_attribute_memory.get(79), 79);
+        assert_eq!(mmu.object_attribute_memory.get(80), 0);
+
+        // only HRAM can be reached by the cpu
+        assert_eq!(mmu.get(0xC100), 0xFF);
+        mmu.set(0xC100, 0xAA);
+        assert_eq!(mmu.get(0xFF46), 0xFF);
+        assert_eq!(mmu.get(0xFF80), 0x12);
+
+        mmu.tick(80 * 4);
+        assert_eq!(mmu.object_attribute_memory.get(159), 159);
+        assert_eq!(mmu.get(0xC100), 0x00);
+        assert_eq!(mmu.get(0xFE05), 5);
+    }
+}
@@ -109,7 +109,8 @@ impl Registers {
 
     pub fn set16(&mut self, r: &Registers16, v: u16) {
         match r {
-            Registers16::AF => self.set_combined(&Registers8::A, &Registers8::F, v),
+            /* the low nibble of F doesn't exist and always reads back 0 */
+            Registers16::AF => self.set_combined(&Registers8::A, &Registers8::F, v & 0xFFF0),
             Registers16::BC => self.set_combined(&Registers8::B, &Registers8::C, v),
             Registers16::DE => self.set_combined(&Registers8::D, &Registers8::E, v),
             Registers16::HL => self.set_combined(&Registers8::H, &Registers8::L, v),
@@ -131,16 +132,16 @@ impl Registers {
     }
 
     pub fn inc_pc(&mut self) {
-        self.pc = self.pc + 1
+        self.pc = self.pc.wrapping_add(1)
     }
 
     pub fn dec_hl(&mut self) {
         let hl = self.get16(&Registers16::HL);
-        self.set16(&Registers16::HL, hl - 1)
+        self.set16(&Registers16::HL, hl.wrapping_sub(1))
     }
 
     pub fn inc_hl(&mut self) {
-        let hl = self.get16(&Registers16::HL) + 1;
+        let hl = self.get16(&Registers16::HL).wrapping_add(1);
         self.set16(&Registers16::HL, hl)
     }
 
@@ -0,0 +1,232 @@
+use std::f64::consts::PI;
+
+/* Input samples are averaged in groups of this many first */
+const DECIMATION: usize = 8;
+
+/* The width of the sinc filter, in decimated samples */
+const TAPS: usize = 32;
+
+/* How finely the filter is positioned between two decimated samples */
+const PHASES: usize = 64;
+
+/* Takes interleaved samples from one rate to another.
+ *
+ * The APU's samples come at ~1MHz, far above anything the host plays, and they're full of sharp
+ * edges that would alias back into the audible range if they were just picked out at the host's
+ * rate. They're band limited on the way down instead, in two steps: averaging groups of
+ * DECIMATION samples, a cheap box filter that knocks out most of what's far above the output
+ * rate, then a Blackman windowed sinc low pass at just under the output's Nyquist frequency that
+ * interpolates the output samples. The sinc is precomputed for PHASES fractional positions.
+ */
+pub struct Resampler {
+    channels: usize,
+    /* decimated samples per output sample */
+    step: f64,
+    kernel: Vec<f32>,
+    /* the running sums of the group being averaged */
+    sums: Vec<f32>,
+    summed: usize,
+    /* decimated samples still needed, interleaved */
+    history: Vec<f32>,
+    /* where the next output sample falls in history, in samples */
+    position: f64,
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn blackman(x: f64, width: f64) -> f64 {
+    let n = x / width + 0.5;
+    if !(0.0..=1.0).contains(&n) {
+        return 0.0;
+    }
+    0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos()
+}
+
+/* One filter per phase, each TAPS long and normalised so a constant comes through unchanged */
+fn make_kernel(step: f64) -> Vec<f32> {
+    let cutoff = 0.9 * (1.0 / step).min(1.0);
+    let mut kernel = Vec::with_capacity(PHASES * TAPS);
+
+    for p in 0..PHASES {
+        let fraction = p as f64 / PHASES as f64;
+
+        let taps: Vec<f64> = (0..TAPS)
+            .map(|k| {
+                let d = k as f64 - (TAPS / 2 - 1) as f64 - fraction;
+                cutoff * sinc(cutoff * d) * blackman(d, TAPS as f64)
+            })
+            .collect();
+
+        let sum: f64 = taps.iter().sum();
+        kernel.extend(taps.iter().map(|t| (t / sum) as f32));
+    }
+
+    kernel
+}
+
+impl Resampler {
+    /* Resamples `input`, interleaved frames of the resampler's channels, appending whatever
+     * output frames can be made so far to `output`.
+     */
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for frame in input.chunks(self.channels) {
+            for (s, v) in self.sums.iter_mut().zip(frame) {
+                *s += v;
+            }
+            self.summed += 1;
+
+            if self.summed == DECIMATION {
+                for s in self.sums.iter_mut() {
+                    self.history.push(*s / DECIMATION as f32);
+                    *s = 0.0;
+                }
+                self.summed = 0;
+                self.interpolate(output);
+            }
+        }
+    }
+
+    fn interpolate(&mut self, output: &mut Vec<f32>) {
+        let available = self.history.len() / self.channels;
+
+        while (self.position as usize) + TAPS / 2 < available {
+            let whole = self.position as usize;
+            let phase = ((self.position - whole as f64) * PHASES as f64) as usize;
+            let taps = &self.kernel[phase * TAPS..(phase + 1) * TAPS];
+
+            /* the filter is centred between history[whole] and history[whole + 1] */
+            let first = whole + 1 - TAPS / 2;
+
+            for c in 0..self.channels {
+                let mut v = 0.0;
+                for (k, t) in taps.iter().enumerate() {
+                    v += t * self.history[(first + k) * self.channels + c];
+                }
+                output.push(v);
+            }
+
+            self.position += self.step;
+        }
+
+        /* drop what no output sample will look at again */
+        let whole = self.position as usize;
+        if whole >= TAPS {
+            let drop = whole + 1 - TAPS / 2;
+            self.history.drain(..drop * self.channels);
+            self.position -= drop as f64;
+        }
+    }
+}
+
+pub fn new(channels: usize, input_rate: u32, output_rate: u32) -> Resampler {
+    let step = input_rate as f64 / DECIMATION as f64 / output_rate as f64;
+
+    Resampler {
+        channels: channels,
+        step: step,
+        kernel: make_kernel(step),
+        sums: vec![0.0; channels],
+        summed: 0,
+        /* start with a filter's width of silence so the first output sample has history */
+        history: vec![0.0; TAPS * channels],
+        position: TAPS as f64 / 2.0,
+    }
+}
+
+/* A one pole high pass filter that takes out the DC offset.
+ *
+ * A channel's DAC outputs -1.0 at level 0 rather than silence, so the mix sits at an offset that
+ * moves as channels start, stop and have their DACs switched on and off. Speakers don't want a
+ * constant, and removing it smoothly keeps those changes from clicking.
+ */
+pub struct DcBlocker {
+    channels: usize,
+    factor: f32,
+    inputs: Vec<f32>,
+    outputs: Vec<f32>,
+}
+
+impl DcBlocker {
+    /* Filters interleaved frames of the blocker's channels in place */
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            for (c, v) in frame.iter_mut().enumerate() {
+                let out = *v - self.inputs[c] + self.factor * self.outputs[c];
+                self.inputs[c] = *v;
+                self.outputs[c] = out;
+                *v = out;
+            }
+        }
+    }
+}
+
+/* The filter's corner sits at a few Hz whatever the rate */
+pub fn new_dc_blocker(channels: usize, rate: u32) -> DcBlocker {
+    DcBlocker {
+        channels: channels,
+        factor: 1.0 - (2.0 * PI * 5.0 / rate as f64) as f32,
+        inputs: vec![0.0; channels],
+        outputs: vec![0.0; channels],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, rate: u32, n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| (2.0 * PI * frequency * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |m, v| v.abs().max(m))
+    }
+
+    #[test]
+    fn test_output_rate() {
+        let mut resampler = new(2, 1048576, 48000);
+        let mut output = Vec::new();
+
+        resampler.process(&vec![0.0; 1048576 * 2], &mut output);
+
+        let frames = output.len() / 2;
+        assert!(frames > 47900 && frames <= 48000, "{}", frames);
+    }
+
+    #[test]
+    fn test_band_limited() {
+        let mut output = Vec::new();
+
+        // 1kHz passes
+        let mut resampler = new(1, 1048576, 48000);
+        resampler.process(&sine(1000.0, 1048576, 1048576 / 4), &mut output);
+        let p = peak(&output[1000..]);
+        assert!(p > 0.97 && p < 1.03, "{}", p);
+
+        // 40kHz is above the output's Nyquist frequency and would alias to 8kHz
+        output.clear();
+        let mut resampler = new(1, 1048576, 48000);
+        resampler.process(&sine(40000.0, 1048576, 1048576 / 4), &mut output);
+        let p = peak(&output[1000..]);
+        assert!(p < 0.01, "{}", p);
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        let mut blocker = new_dc_blocker(2, 48000);
+        let mut samples = vec![0.5; 48000 * 2];
+
+        blocker.process(&mut samples);
+
+        assert!(samples[0] == 0.5);
+        assert!(peak(&samples[48000..]) < 0.01);
+    }
+}
@@ -0,0 +1,182 @@
+/* Save files hold the battery backed ram of a cartridge so games keep their progress between
+ * sessions. The layout matches what BGB and VBA write so saves can move between emulators:
+ *
+ * [ cartridge ram ][ rtc (MBC3 timer cartridges only) ]
+ *
+ * The rtc block is 48 bytes of little endian integers:
+ *
+ * 5 x u32: seconds, minutes, hours, days low, days high
+ * 5 x u32: the latched copies of the same registers
+ * 1 x u64: unix timestamp of when the save was written
+ *
+ * Some emulators write the timestamp as a u32 making the block 44 bytes, those load fine too.
+ */
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use device::cartridge::Cartridge;
+
+fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn read_registers(bytes: &[u8]) -> [u8; 5] {
+    let mut registers = [0; 5];
+    for i in 0..5 {
+        registers[i] = read_u32(&bytes[i * 4..]) as u8;
+    }
+    registers
+}
+
+fn write_u32(out: &mut Vec<u8>, v: u32) {
+    for i in 0..4 {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+fn encode(cartridge: &Cartridge) -> Vec<u8> {
+    let mut out = cartridge.ram().to_vec();
+
+    if let Some(rtc) = cartridge.rtc() {
+        for r in rtc.registers().iter() {
+            write_u32(&mut out, *r as u32);
+        }
+        for r in rtc.latched().iter() {
+            write_u32(&mut out, *r as u32);
+        }
+
+        let timestamp = now();
+        write_u32(&mut out, timestamp as u32);
+        write_u32(&mut out, (timestamp >> 32) as u32);
+    }
+
+    out
+}
+
+/* Loads the save at `path` into the cartridge, a missing save file just means a fresh game. When
+ * `wall_clock` is set the rtc is moved forward by however long it's been since the save was
+ * written, the same as if the cartridge battery had kept the clock running.
+ */
+pub fn load(path: &Path, cartridge: &mut Cartridge, wall_clock: bool) -> io::Result<()> {
+    let mut data = Vec::new();
+
+    match File::open(path) {
+        Ok(mut f) => f.read_to_end(&mut data)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let ram_size = cartridge.ram().len().min(data.len());
+    cartridge.load_ram(&data[..ram_size]);
+
+    let rtc_data = &data[ram_size..];
+
+    if let Some(rtc) = cartridge.rtc_mut() {
+        if rtc_data.len() >= 44 {
+            rtc.restore(read_registers(&rtc_data[0..20]), read_registers(&rtc_data[20..40]));
+
+            let mut timestamp = read_u32(&rtc_data[40..44]) as u64;
+            if rtc_data.len() >= 48 {
+                timestamp |= (read_u32(&rtc_data[44..48]) as u64) << 32;
+            }
+
+            if wall_clock && timestamp < now() {
+                rtc.advance_seconds(now() - timestamp);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/* Writes the cartridge's ram (and clock) to `path`. To make sure a crash or a kill never leaves a
+ * truncated save behind, the data goes to a temporary file next to the save which is flushed to
+ * disk and then renamed over the old save in one step.
+ */
+pub fn write(path: &Path, cartridge: &Cartridge) -> io::Result<()> {
+    let data = encode(cartridge);
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    {
+        let mut f = File::create(&tmp)?;
+        f.write_all(&data)?;
+        f.sync_all()?;
+    }
+
+    fs::rename(&tmp, path)?;
+
+    /* Flush the directory entry too so the rename itself survives a power loss, not every
+     * platform lets us open a directory so this is best effort.
+     */
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    if let Ok(d) = File::open(dir) {
+        let _ = d.sync_all();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use device::cartridge;
+    use device::cartridge_header;
+    use device::Device;
+
+    fn mbc3_cartridge() -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x10;
+        rom[0x0149] = 0x02;
+        cartridge::new(cartridge_header::parse(&rom).unwrap(), rom).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = env::temp_dir().join(format!("agbe-save-test-{}.sav", ::std::process::id()));
+
+        let mut c = mbc3_cartridge();
+        c.set(0x0000, 0x0A);
+        c.set_ram(0x0123, 0x45);
+        c.set(0x4000, 0x09);
+        c.set_ram(0x0000, 12);
+
+        write(&path, &c).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0x2000 + 48);
+
+        let mut loaded = mbc3_cartridge();
+        load(&path, &mut loaded, false).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        loaded.set(0x0000, 0x0A);
+        assert_eq!(loaded.get_ram(0x0123), 0x45);
+
+        loaded.set(0x4000, 0x09);
+        loaded.set(0x6000, 0x00);
+        loaded.set(0x6000, 0x01);
+        assert_eq!(loaded.get_ram(0x0000), 12);
+    }
+
+    #[test]
+    fn test_missing_save_is_not_an_error() {
+        let path = env::temp_dir().join("agbe-save-test-does-not-exist.sav");
+        let mut c = mbc3_cartridge();
+        assert!(load(&path, &mut c, true).is_ok());
+    }
+}
@@ -22,11 +22,12 @@ impl Tile {
 
         let mut arr = [0;8];
 
+        /* bit 7 is the leftmost pixel, the bottom byte holds the high bit of each pixel */
         for i in 0..8 {
-            let m = 0x01 << i;
-            let tb = (top_byte & m) >> i;
-            let bb = (bottom_byte & m) >> i;
-            arr[i as usize] = tb + bb;
+            let m = 0x80 >> i;
+            let tb = (top_byte & m) >> (7 - i);
+            let bb = (bottom_byte & m) >> (7 - i);
+            arr[i as usize] = tb | (bb << 1);
         }
 
         arr
@@ -42,3 +43,18 @@ impl Tile {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_row() {
+        let mut storage = [0; 16];
+        storage[0] = 0b0100_1110;
+        storage[1] = 0b1000_1011;
+        let tile = Tile { storage: storage };
+
+        assert_eq!(tile.get_row(0), [2, 1, 0, 0, 3, 1, 3, 2]);
+    }
+}
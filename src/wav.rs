@@ -0,0 +1,73 @@
+/* Writes 16 bit PCM WAV files.
+ *
+ * [ "RIFF" | size | "WAVE" ][ "fmt " | 16 | format ][ "data" | size | samples ]
+ *
+ * All the sizes and samples are little endian, samples are interleaved a frame of every channel
+ * at a time. Samples come in as -1.0 to 1.0 and are clipped to that range.
+ */
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn write_u16(out: &mut Vec<u8>, v: u16) {
+    out.push(v as u8);
+    out.push((v >> 8) as u8);
+}
+
+fn write_u32(out: &mut Vec<u8>, v: u32) {
+    for i in 0..4 {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+pub fn encode(rate: u32, channels: u16, samples: &[f32]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_size as usize);
+
+    out.extend_from_slice(b"RIFF");
+    write_u32(&mut out, 36 + data_size);
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    write_u32(&mut out, 16);
+    /* PCM */
+    write_u16(&mut out, 1);
+    write_u16(&mut out, channels);
+    write_u32(&mut out, rate);
+    /* bytes per second and per frame */
+    write_u32(&mut out, rate * channels as u32 * 2);
+    write_u16(&mut out, channels * 2);
+    /* bits per sample */
+    write_u16(&mut out, 16);
+
+    out.extend_from_slice(b"data");
+    write_u32(&mut out, data_size);
+    for s in samples {
+        let v = (s.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+        write_u16(&mut out, v as u16);
+    }
+
+    out
+}
+
+pub fn write(path: &Path, rate: u32, channels: u16, samples: &[f32]) -> io::Result<()> {
+    fs::write(path, encode(rate, channels, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let wav = encode(48000, 2, &[0.0, 1.0, -1.0, 2.0]);
+
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &[44, 0, 0, 0]);
+        assert_eq!(&wav[22..24], &[2, 0]);
+        assert_eq!(&wav[24..28], &[0x80, 0xBB, 0, 0]);
+        assert_eq!(&wav[40..44], &[8, 0, 0, 0]);
+        assert_eq!(&wav[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+    }
+}